@@ -0,0 +1,133 @@
+use protocol::User;
+use sdk::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// how long a cached roster counts as fresh.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// the last roster fetched from one server, kept in
+/// `<cache dir>/ccweb/<server>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cache {
+    pub base_url: String,
+    pub etag: Option<String>,
+    /// unix seconds of the last time the server confirmed this roster.
+    pub fetched_at: u64,
+    pub users: Vec<User>,
+}
+
+impl Cache {
+    pub fn empty(base_url: &str) -> Self {
+        Cache {
+            base_url: base_url.to_string(),
+            etag: None,
+            fetched_at: 0,
+            users: Vec::new(),
+        }
+    }
+
+    /// the cache for `base_url`, if there is a readable one.
+    pub fn load(base_url: &str) -> Option<Self> {
+        let text = fs::read_to_string(path(base_url)?).ok()?;
+        let cache: Cache = serde_json::from_str(&text).ok()?;
+        (cache.base_url == base_url).then_some(cache)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = path(&self.base_url)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// checks the server for a newer roster, sending the etag so an unchanged
+    /// roster costs a 304. returns whether the users changed.
+    pub async fn refresh(&mut self, client: &Client) -> Result<bool, sdk::Error> {
+        let changed = match client.list_users_since(self.etag.as_deref()).await? {
+            Some(list) => {
+                let changed = list.users != self.users;
+                self.users = list.users;
+                self.etag = list.etag;
+                changed
+            }
+            None => false,
+        };
+        self.fetched_at = now();
+        Ok(changed)
+    }
+
+    pub fn is_stale(&self, ttl: Duration) -> bool {
+        self.age() > ttl
+    }
+
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.fetched_at))
+    }
+
+    /// "cached 5m ago", for marking stale output.
+    pub fn describe_age(&self) -> String {
+        let secs = self.age().as_secs();
+        let age = match secs {
+            0..=59 => format!("{}s", secs),
+            60..=3599 => format!("{}m", secs / 60),
+            3600..=86399 => format!("{}h", secs / 3600),
+            _ => format!("{}d", secs / 86400),
+        };
+        format!("cached {} ago", age)
+    }
+}
+
+/// one file per server so profiles don't clobber each other.
+fn path(base_url: &str) -> Option<PathBuf> {
+    let name: String = base_url
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    dirs::cache_dir().map(|dir| dir.join("ccweb").join(format!("{}.json", name)))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched(secs_ago: u64) -> Cache {
+        Cache {
+            fetched_at: now() - secs_ago,
+            ..Cache::empty("http://localhost:3000")
+        }
+    }
+
+    #[test]
+    fn cache_goes_stale_after_the_ttl() {
+        let ttl = Duration::from_secs(300);
+        assert!(!fetched(10).is_stale(ttl));
+        assert!(fetched(301).is_stale(ttl));
+        // never fetched
+        assert!(Cache::empty("http://localhost:3000").is_stale(ttl));
+    }
+
+    #[test]
+    fn age_is_described_in_the_largest_unit() {
+        assert_eq!(fetched(5).describe_age(), "cached 5s ago");
+        assert_eq!(fetched(150).describe_age(), "cached 2m ago");
+        assert_eq!(fetched(2 * 3600).describe_age(), "cached 2h ago");
+        assert_eq!(fetched(3 * 86400).describe_age(), "cached 3d ago");
+    }
+}
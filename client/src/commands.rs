@@ -0,0 +1,382 @@
+use crate::{
+    cache::Cache,
+    output::{self, Output, OutputFormat},
+    progress, search, sync, tui,
+};
+use clap::{Parser, Subcommand};
+use protocol::{CreateUserRequest, Language};
+use sdk::Client;
+use std::{
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration,
+};
+
+/// exit codes for the non-interactive commands, so scripts can tell
+/// failures apart without parsing stderr.
+pub mod exit {
+    pub const FAILURE: u8 = 1;
+    /// bad arguments or config, a missing api key, or a refused confirmation.
+    pub const USAGE: u8 = 2;
+    pub const NOT_FOUND: u8 = 3;
+    /// the user already exists, or isn't in the state the command needs.
+    pub const CONFLICT: u8 = 4;
+    pub const AUTH: u8 = 5;
+    /// the server couldn't be reached or didn't answer in time.
+    pub const UNAVAILABLE: u8 = 6;
+    /// ctrl-c, as a shell would report it.
+    pub const INTERRUPTED: u8 = 130;
+}
+
+#[derive(Parser)]
+#[command(name = "ccweb", version, about = "CCweb roster client")]
+#[command(after_help = "Run without a command for the interactive prompt.\n\n\
+Exit codes: 0 ok, 1 error, 2 usage, 3 not found, 4 conflict, 5 auth, 6 unavailable, 130 cancelled")]
+pub struct Cli {
+    /// profile from the config file.
+    #[arg(long, short, env = "CCWEB_PROFILE", global = true)]
+    pub profile: Option<String>,
+
+    /// config file [default: <config dir>/ccweb/config.toml]
+    #[arg(long, env = "CCWEB_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// server to talk to, overriding the profile.
+    #[arg(long, env = "CCWEB_URL", global = true)]
+    pub url: Option<String>,
+
+    /// overrides the profile; API_KEY is used when neither sets one.
+    #[arg(long, global = true)]
+    pub api_key: Option<String>,
+
+    /// seconds to wait for a response [default: the profile's, else 30]
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// pem file of extra ca certificates to trust, for a server with a
+    /// self-signed certificate [default: the profile's]
+    #[arg(long, env = "CCWEB_CA_BUNDLE", global = true)]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// table, json, yaml or plain [default: the profile's, else table on a
+    /// terminal and json when piped]
+    #[arg(long, short, global = true)]
+    pub output: Option<OutputFormat>,
+
+    /// answer `search`, `user get` and `user list` from the cached roster
+    /// without contacting the server.
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// manage users.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// browse and edit the roster full screen.
+    Tui,
+    /// fuzzy search the cached roster, e.g. `search --lang haskell` for
+    /// everyone who knows haskell.
+    Search {
+        query: Option<String>,
+        /// only users who know all of these, comma separated.
+        #[arg(long = "lang", value_delimiter = ',')]
+        languages: Vec<Language>,
+    },
+    /// make the server match a yaml roster file, after showing the plan.
+    Sync {
+        file: PathBuf,
+        /// also delete users that aren't in the file.
+        #[arg(long)]
+        prune: bool,
+        /// print the plan and stop.
+        #[arg(long)]
+        dry_run: bool,
+        /// apply without asking.
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum UserCommand {
+    /// print a user.
+    Get { username: String },
+    /// print every user.
+    List,
+    /// add a user.
+    Create {
+        username: String,
+        /// comma separated, e.g. `rust,c`.
+        #[arg(long = "lang", value_delimiter = ',')]
+        languages: Vec<Language>,
+        #[arg(long = "discord")]
+        discord_id: Option<String>,
+    },
+    /// add languages to a user, e.g. `add-lang fork go,c`.
+    AddLang {
+        username: String,
+        #[arg(required = true, value_delimiter = ',')]
+        languages: Vec<Language>,
+    },
+    /// remove languages from a user.
+    RmLang {
+        username: String,
+        #[arg(required = true, value_delimiter = ',')]
+        languages: Vec<Language>,
+    },
+    /// soft delete a user. asks first unless --yes is given.
+    Delete {
+        username: String,
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// bring back a deleted user.
+    Restore { username: String },
+    /// issue a personal token that can change only this user's languages.
+    /// issuing again replaces the old one.
+    Token {
+        username: String,
+        /// revoke the user's token instead.
+        #[arg(long)]
+        revoke: bool,
+    },
+}
+
+/// settings every command shares.
+pub struct Options {
+    pub format: OutputFormat,
+    pub offline: bool,
+    /// how long the cached roster counts as fresh.
+    pub cache_ttl: Duration,
+}
+
+pub async fn run(client: &Client, options: &Options, command: Command) -> ExitCode {
+    let mut command = match command {
+        Command::User(command) => command,
+        Command::Tui => return tui::run(client).await,
+        Command::Search { query, languages } => {
+            return search::run(client, options, query.as_deref(), &languages).await
+        }
+        Command::Sync {
+            file,
+            prune,
+            dry_run,
+            yes,
+        } => return sync::run(client, &file, prune, dry_run, yes).await,
+    };
+
+    let base_url = client.base_url().as_str();
+    let cache = Cache::load(base_url);
+    if options.offline {
+        return match cache {
+            Some(cache) => run_cached(&cache, options, &command),
+            None => {
+                eprintln!("no cached roster for {}", base_url);
+                ExitCode::from(exit::UNAVAILABLE)
+            }
+        };
+    }
+
+    if let UserCommand::Delete { username, yes } = &mut command {
+        if !*yes && !confirm(&format!("delete {}?", username)) {
+            return ExitCode::from(exit::USAGE);
+        }
+        *yes = true;
+    }
+
+    let reads = matches!(command, UserCommand::Get { .. } | UserCommand::List);
+    // the roster answers reads without waiting on the server; a stale one
+    // says so and is brought up to date afterwards. a user it doesn't have
+    // yet may have been created since, so that still asks
+    if let Some(cached) = cache.as_ref().filter(|_| reads) {
+        if let Some(output) = from_cache(cached, &command) {
+            print(options, &output);
+            if cached.is_stale(options.cache_ttl) {
+                eprintln!("{}, refreshing", cached.describe_age());
+                spawn_refresh(client, cache.clone(), base_url).await.ok();
+            }
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    // a read refreshes the cache alongside the request; a write has to land
+    // first, so its refresh starts once the server has answered
+    let mut refresh = reads.then(|| spawn_refresh(client, cache.clone(), base_url));
+    let label = format!("waiting for {}", base_url);
+    let code = match progress::spin(&label, run_user(client, command.clone())).await {
+        Some(Ok(output)) => {
+            print(options, &output);
+            ExitCode::SUCCESS
+        }
+        // reads can still be answered from the cache when the server is away
+        Some(Err(e @ (sdk::Error::Timeout | sdk::Error::Connect(_))))
+            if reads && cache.is_some() =>
+        {
+            eprintln!("{}", e);
+            return run_cached(cache.as_ref().expect("checked above"), options, &command);
+        }
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            return exit_code(&e);
+        }
+        None => {
+            eprintln!("cancelled");
+            return ExitCode::from(exit::INTERRUPTED);
+        }
+    };
+
+    // the output is already out; this only keeps the process alive until the
+    // cache is up to date for next time
+    let refresh = refresh.get_or_insert_with(|| spawn_refresh(client, cache, base_url));
+    refresh.await.ok();
+    code
+}
+
+/// brings the cached roster up to date on a task of its own.
+fn spawn_refresh(
+    client: &Client,
+    cache: Option<Cache>,
+    base_url: &str,
+) -> tokio::task::JoinHandle<()> {
+    let client = client.clone();
+    let mut cache = cache.unwrap_or_else(|| Cache::empty(base_url));
+    tokio::spawn(async move {
+        if cache.refresh(&client).await.is_ok() {
+            cache.save().ok();
+        }
+    })
+}
+
+/// a read answered from the roster, `None` when it doesn't have the user.
+fn from_cache(cache: &Cache, command: &UserCommand) -> Option<Output> {
+    match command {
+        UserCommand::Get { username } => cache
+            .users
+            .iter()
+            .find(|u| u.username == *username)
+            .map(|user| Output::User(user.clone())),
+        UserCommand::List => Some(Output::Users(cache.users.clone())),
+        _ => None,
+    }
+}
+
+fn print(options: &Options, output: &Output) {
+    // a closed pipe (`| head`) isn't worth a panic
+    writeln!(
+        std::io::stdout(),
+        "{}",
+        output::render(options.format, output)
+    )
+    .ok();
+}
+
+/// answers a read from the cache, marking the output as possibly stale.
+fn run_cached(cache: &Cache, options: &Options, command: &UserCommand) -> ExitCode {
+    let Some(output) = from_cache(cache, command) else {
+        match command {
+            UserCommand::Get { username } => {
+                eprintln!(
+                    "{} is not in the roster ({})",
+                    username,
+                    cache.describe_age()
+                );
+                return ExitCode::from(exit::NOT_FOUND);
+            }
+            _ => {
+                eprintln!("only reads work offline");
+                return ExitCode::from(exit::UNAVAILABLE);
+            }
+        }
+    };
+    eprintln!("offline: {}", cache.describe_age());
+    print(options, &output);
+    ExitCode::SUCCESS
+}
+
+/// runs one user command. deletes are expected to be confirmed already.
+pub async fn run_user(client: &Client, command: UserCommand) -> Result<Output, sdk::Error> {
+    let output = match command {
+        UserCommand::Get { username } => Output::User(client.get_user(&username).await?),
+        UserCommand::List => Output::Users(client.list_users().await?),
+        UserCommand::Create {
+            username,
+            languages,
+            discord_id,
+        } => {
+            let request = CreateUserRequest {
+                username,
+                languages,
+                discord_id,
+            };
+            Output::User(client.create_user(&request).await?)
+        }
+        UserCommand::AddLang {
+            username,
+            languages,
+        } => Output::User(client.add_languages(&username, &languages).await?),
+        UserCommand::RmLang {
+            username,
+            languages,
+        } => Output::User(client.remove_languages(&username, &languages).await?),
+        UserCommand::Delete { username, .. } => {
+            client.delete_user(&username).await?;
+            Output::Message(format!("deleted {}", username))
+        }
+        UserCommand::Restore { username } => Output::User(client.restore_user(&username).await?),
+        UserCommand::Token {
+            username,
+            revoke: true,
+        } => {
+            client.revoke_token(&username).await?;
+            Output::Message(format!("revoked the token of {}", username))
+        }
+        UserCommand::Token { username, .. } => {
+            Output::Message(client.issue_token(&username).await?.token)
+        }
+    };
+    Ok(output)
+}
+
+pub fn exit_code(error: &sdk::Error) -> ExitCode {
+    let code = match error {
+        sdk::Error::Api { status: 404, .. } => exit::NOT_FOUND,
+        sdk::Error::Api { status: 409, .. } => exit::CONFLICT,
+        sdk::Error::Api {
+            status: 401 | 403, ..
+        } => exit::AUTH,
+        sdk::Error::Api {
+            status: 400 | 422, ..
+        }
+        | sdk::Error::InvalidUrl(_)
+        | sdk::Error::InvalidCertificate(_) => exit::USAGE,
+        sdk::Error::Timeout | sdk::Error::Connect(_) => exit::UNAVAILABLE,
+        _ => exit::FAILURE,
+    };
+    ExitCode::from(code)
+}
+
+/// asks on the terminal; anything that isn't a terminal never confirms, so
+/// scripts have to pass --yes.
+pub fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "refusing to {} without --yes",
+            question.trim_end_matches('?')
+        );
+        return false;
+    }
+
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush().ok();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
@@ -0,0 +1,199 @@
+use crate::output::OutputFormat;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// used when no flag, environment variable or profile names a server.
+pub const DEFAULT_URL: &str = "http://localhost:3000";
+
+/// `config.toml`, e.g.
+///
+/// ```toml
+/// default_profile = "local"
+///
+/// [profiles.local]
+/// base_url = "http://localhost:3000"
+/// api_key = "dev"
+///
+/// [profiles.prod]
+/// base_url = "https://ccweb.example.com"
+/// key_command = "pass show ccweb/prod"
+/// output = "plain"
+/// timeout = 10
+/// cache_ttl = 600
+///
+/// [profiles.lab]
+/// base_url = "https://ccweb.lab.internal:3000"
+/// ca_bundle = "/etc/ccweb/lab-ca.pem"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// used when neither `--profile` nor `CCWEB_PROFILE` is given.
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    /// run through the shell; its trimmed stdout is the key, so the key can
+    /// live in a password manager instead of the config file.
+    pub key_command: Option<String>,
+    pub output: Option<OutputFormat>,
+    /// seconds to wait for a response.
+    pub timeout: Option<u64>,
+    /// seconds the cached roster counts as fresh.
+    pub cache_ttl: Option<u64>,
+    /// pem file of extra ca certificates to trust.
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// everything the client needs to talk to a server, after flags, environment
+/// and the chosen profile have been merged.
+#[derive(Debug)]
+pub struct Settings {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub output: Option<OutputFormat>,
+    pub timeout: Option<u64>,
+    pub cache_ttl: Option<u64>,
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// values given on the command line (or their environment variables), which
+/// win over the profile.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub profile: Option<String>,
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownProfile(String),
+    KeyCommand(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+            ConfigError::UnknownProfile(name) => write!(f, "no profile named {:?}", name),
+            ConfigError::KeyCommand(command, reason) => {
+                write!(f, "key_command {:?} failed: {}", command, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// `$XDG_CONFIG_HOME/ccweb/config.toml` or the platform equivalent.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ccweb").join("config.toml"))
+}
+
+/// reads the config file. a missing file is only an error when the path was
+/// given explicitly.
+pub fn load(path: Option<&Path>) -> Result<ConfigFile, ConfigError> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(ConfigFile::default()),
+        },
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => {
+            return Ok(ConfigFile::default())
+        }
+        Err(e) => return Err(ConfigError::Read(path, e)),
+    };
+    toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+}
+
+impl ConfigFile {
+    /// merges the overrides with the selected profile. the profile is the
+    /// one asked for, else `default_profile`, else one named `default`.
+    pub fn resolve(&self, overrides: Overrides) -> Result<Settings, ConfigError> {
+        let name = match overrides.profile.or_else(|| self.default_profile.clone()) {
+            Some(name) if self.profiles.contains_key(&name) => Some(name),
+            Some(name) => return Err(ConfigError::UnknownProfile(name)),
+            None if self.profiles.contains_key("default") => Some("default".to_string()),
+            None => None,
+        };
+        let profile = name
+            .as_ref()
+            .map(|name| self.profiles[name].clone())
+            .unwrap_or_default();
+
+        let base_url = overrides
+            .url
+            .or(profile.base_url)
+            .unwrap_or_else(|| DEFAULT_URL.to_string());
+
+        // API_KEY from the environment or .env comes last so a stray .env
+        // can't send one server's key to another
+        let api_key = match (overrides.api_key, profile.api_key, profile.key_command) {
+            (Some(key), _, _) | (None, Some(key), _) => Some(key),
+            (None, None, Some(command)) => Some(run_key_command(&command)?),
+            (None, None, None) => env::var("API_KEY").ok(),
+        };
+
+        Ok(Settings {
+            base_url,
+            api_key,
+            output: profile.output,
+            timeout: profile.timeout,
+            cache_ttl: profile.cache_ttl,
+            ca_bundle: profile.ca_bundle,
+        })
+    }
+}
+
+fn run_key_command(command: &str) -> Result<String, ConfigError> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    // only stdout is captured, so password managers can still prompt
+    let output = shell
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| ConfigError::KeyCommand(command.to_string(), e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(ConfigError::KeyCommand(
+            command.to_string(),
+            output.status.to_string(),
+        ));
+    }
+    let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if key.is_empty() {
+        return Err(ConfigError::KeyCommand(
+            command.to_string(),
+            "printed nothing".to_string(),
+        ));
+    }
+    Ok(key)
+}
@@ -0,0 +1,251 @@
+use crate::{
+    commands::{self, UserCommand},
+    output::{self, OutputFormat},
+    progress,
+};
+use clap::{CommandFactory, Parser};
+use protocol::Language;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    history::DefaultHistory,
+    Context, Editor, Helper, Highlighter, Hinter, Validator,
+};
+use sdk::Client;
+use std::{fs, path::PathBuf, process::ExitCode};
+
+const SHELL: &str = "[CCWC] > ";
+
+/// one line typed at the prompt. the user commands are the same as
+/// `ccweb user ...`, without the `user`.
+#[derive(Parser)]
+#[command(multicall = true, disable_help_subcommand = true)]
+enum ReplCommand {
+    #[command(flatten)]
+    User(UserCommand),
+    /// reload usernames for tab completion.
+    Refresh,
+    /// list commands, or show the options of one.
+    Help { command: Option<String> },
+    /// leave the shell. ctrl-d works too.
+    #[command(alias = "quit")]
+    Exit,
+}
+
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ReplHelper {
+    commands: Vec<String>,
+    usernames: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let mut start = before.rfind(' ').map_or(0, |i| i + 1);
+        let mut word = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let candidates: Vec<String> = match words.as_slice() {
+            [] | ["help"] => self.commands.clone(),
+            [.., "--lang"] | ["add-lang" | "rm-lang", _, ..] => {
+                // only the part after the last comma is being typed
+                if let Some(i) = word.rfind(',') {
+                    start += i + 1;
+                    word = &word[i + 1..];
+                }
+                Language::ALL
+                    .iter()
+                    .map(|l| l.to_string().to_lowercase())
+                    .collect()
+            }
+            [command] if takes_username(command) => self.usernames.clone(),
+            _ => Vec::new(),
+        };
+
+        let prefix = word.to_lowercase();
+        let matches = candidates
+            .into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&prefix))
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+fn takes_username(command: &str) -> bool {
+    matches!(
+        command,
+        "get" | "add-lang" | "rm-lang" | "delete" | "restore" | "token"
+    )
+}
+
+/// a prompt that runs commands until `exit` or ctrl-d.
+pub async fn run(client: Client, format: OutputFormat) -> ExitCode {
+    let mut editor = match Editor::<ReplHelper, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("{}could not start the prompt: {}", SHELL, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    editor.set_helper(Some(ReplHelper {
+        commands: command_names(),
+        usernames: fetch_usernames(&client).await,
+    }));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // no history yet is fine
+        editor.load_history(path).ok();
+    }
+
+    println!(
+        "{}connected to {}, type 'help' for commands",
+        SHELL,
+        client.base_url()
+    );
+    loop {
+        let line = match editor.readline(SHELL) {
+            Ok(line) => line,
+            // ctrl-c drops the line, like a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}{}", SHELL, e);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line).ok();
+
+        let Some(args) = shlex::split(line) else {
+            println!("unbalanced quotes");
+            continue;
+        };
+        let command = match ReplCommand::try_parse_from(args) {
+            Ok(command) => command,
+            Err(e) => {
+                e.print().ok();
+                continue;
+            }
+        };
+
+        match command {
+            ReplCommand::Exit => break,
+            ReplCommand::Help { command } => print_help(command.as_deref()),
+            ReplCommand::Refresh => {
+                let usernames = fetch_usernames(&client).await;
+                println!("{} usernames loaded", usernames.len());
+                if let Some(helper) = editor.helper_mut() {
+                    helper.usernames = usernames;
+                }
+            }
+            ReplCommand::User(mut command) => {
+                if let UserCommand::Delete { username, yes } = &mut command {
+                    if !*yes {
+                        let answer = editor
+                            .readline(&format!("delete {}? [y/N] ", username))
+                            .unwrap_or_default();
+                        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                            continue;
+                        }
+                        *yes = true;
+                    }
+                }
+                let changes_usernames = matches!(
+                    command,
+                    UserCommand::Create { .. }
+                        | UserCommand::Delete { .. }
+                        | UserCommand::Restore { .. }
+                );
+
+                let label = format!("waiting for {}", client.base_url());
+                match progress::spin(&label, commands::run_user(&client, command)).await {
+                    Some(Ok(output)) => println!("{}", output::render(format, &output)),
+                    Some(Err(e)) => println!("{}request failed: {}", SHELL, e),
+                    None => println!("{}cancelled", SHELL),
+                }
+
+                if changes_usernames {
+                    let usernames = fetch_usernames(&client).await;
+                    if let Some(helper) = editor.helper_mut() {
+                        helper.usernames = usernames;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("{}could not save history: {}", SHELL, e);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// usernames for completion; completion just goes without them when the
+/// server can't be reached or ctrl-c is pressed.
+async fn fetch_usernames(client: &Client) -> Vec<String> {
+    match progress::spin("loading usernames", client.list_users()).await {
+        Some(Ok(users)) => users.into_iter().map(|u| u.username).collect(),
+        Some(Err(e)) => {
+            println!("{}could not load usernames: {}", SHELL, e);
+            Vec::new()
+        }
+        None => Vec::new(),
+    }
+}
+
+fn command_names() -> Vec<String> {
+    ReplCommand::command()
+        .get_subcommands()
+        .flat_map(|c| std::iter::once(c.get_name()).chain(c.get_all_aliases()))
+        .map(str::to_string)
+        .collect()
+}
+
+fn print_help(command: Option<&str>) {
+    let mut repl = ReplCommand::command();
+    if let Some(name) = command {
+        match repl.find_subcommand_mut(name) {
+            Some(subcommand) => println!("{}", subcommand.render_long_help()),
+            None => println!("no command named '{}'", name),
+        }
+        return;
+    }
+
+    println!("commands:");
+    for subcommand in repl.get_subcommands() {
+        let about = subcommand
+            .get_about()
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        println!("  {:<10} {}", subcommand.get_name(), about);
+    }
+    println!();
+    println!(
+        "'help <command>' shows its options. tab completes commands, usernames and languages."
+    );
+}
+
+/// `<data dir>/ccweb/history.txt`
+fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("ccweb").join("history.txt"))
+}
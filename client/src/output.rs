@@ -0,0 +1,190 @@
+use crossterm::style::{Color, Stylize};
+use protocol::{Language, User};
+use serde::Deserialize;
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// aligned columns with colored language badges.
+    Table,
+    /// the server's objects as they came, for `jq`.
+    Json,
+    Yaml,
+    /// one tab separated line per user, for `cut` and friends.
+    Plain,
+}
+
+impl OutputFormat {
+    /// table for a person at a terminal, json for anything else.
+    pub fn detect() -> Self {
+        if io::stdout().is_terminal() {
+            OutputFormat::Table
+        } else {
+            OutputFormat::Json
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "plain" => Ok(OutputFormat::Plain),
+            other => Err(format!("unknown output format {:?}", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Yaml => write!(f, "yaml"),
+            OutputFormat::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+/// what a command produced, before it's rendered.
+pub enum Output {
+    User(User),
+    Users(Vec<User>),
+    Message(String),
+}
+
+pub fn render(format: OutputFormat, output: &Output) -> String {
+    match (format, output) {
+        (_, Output::Message(message)) => message.clone(),
+        (OutputFormat::Table, Output::User(user)) => table(std::slice::from_ref(user)),
+        (OutputFormat::Table, Output::Users(users)) => table(users),
+        (OutputFormat::Json, Output::User(user)) => to_json(user),
+        (OutputFormat::Json, Output::Users(users)) => to_json(users),
+        (OutputFormat::Yaml, Output::User(user)) => to_yaml(user),
+        (OutputFormat::Yaml, Output::Users(users)) => to_yaml(users),
+        (OutputFormat::Plain, Output::User(user)) => plain_line(user),
+        (OutputFormat::Plain, Output::Users(users)) => {
+            users.iter().map(plain_line).collect::<Vec<_>>().join("\n")
+        }
+    }
+}
+
+fn table(users: &[User]) -> String {
+    if users.is_empty() {
+        return "no users".to_string();
+    }
+
+    let color = use_color();
+    let headers = ["USERNAME", "LANGUAGES", "DISCORD ID"];
+    // (visible width, rendered text) so ansi codes don't throw the padding off
+    let rows: Vec<[(usize, String); 3]> = users
+        .iter()
+        .map(|user| {
+            [
+                (user.username.chars().count(), user.username.clone()),
+                badges(&user.languages, color),
+                (user.discord_id.chars().count(), user.discord_id.clone()),
+            ]
+        })
+        .collect();
+
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, (len, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(*len);
+        }
+    }
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    let header = headers
+        .iter()
+        .zip(widths)
+        .map(|(h, w)| format!("{:<w$}", h))
+        .collect::<Vec<_>>()
+        .join("  ");
+    lines.push(if color {
+        header.trim_end().bold().to_string()
+    } else {
+        header.trim_end().to_string()
+    });
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|((len, text), w)| format!("{}{}", text, " ".repeat(w - len)))
+            .collect::<Vec<_>>()
+            .join("  ");
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+/// each language as a colored badge, or `[Name]` without color.
+fn badges(languages: &[Language], color: bool) -> (usize, String) {
+    let width = languages
+        .iter()
+        .map(|l| l.to_string().len() + 2)
+        .sum::<usize>()
+        + languages.len().saturating_sub(1);
+    let text = languages
+        .iter()
+        .map(|&language| {
+            if color {
+                let (bg, fg) = badge_colors(language);
+                format!(" {} ", language).with(fg).on(bg).to_string()
+            } else {
+                format!("[{}]", language)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    (width, text)
+}
+
+/// the same language always gets the same color.
+pub fn badge_colors(language: Language) -> (Color, Color) {
+    const PALETTE: [(Color, Color); 8] = [
+        (Color::DarkRed, Color::White),
+        (Color::DarkGreen, Color::White),
+        (Color::DarkYellow, Color::Black),
+        (Color::DarkBlue, Color::White),
+        (Color::DarkMagenta, Color::White),
+        (Color::DarkCyan, Color::Black),
+        (Color::Grey, Color::Black),
+        (Color::Yellow, Color::Black),
+    ];
+    PALETTE[language as usize % PALETTE.len()]
+}
+
+/// color only on a terminal, and never when `NO_COLOR` is set.
+fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+fn plain_line(user: &User) -> String {
+    let languages: Vec<String> = user.languages.iter().map(|l| l.to_string()).collect();
+    format!(
+        "{}\t{}\t{}",
+        user.username,
+        languages.join(","),
+        user.discord_id
+    )
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("response always serializes")
+}
+
+fn to_yaml<T: serde::Serialize>(value: &T) -> String {
+    let yaml = serde_yaml::to_string(value).expect("response always serializes");
+    yaml.trim_end().to_string()
+}
@@ -0,0 +1,56 @@
+use crossterm::{
+    cursor::MoveToColumn,
+    queue,
+    style::Print,
+    terminal::{Clear, ClearType},
+};
+use std::{
+    future::Future,
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const TICK: Duration = Duration::from_millis(80);
+/// quick requests finish before the spinner shows up at all.
+const DELAY: Duration = Duration::from_millis(150);
+
+/// runs `future` with a spinner on stderr. returns `None` if ctrl-c was
+/// pressed first, in which case the future is dropped, cancelling the
+/// request.
+pub async fn spin<F: Future>(label: &str, future: F) -> Option<F::Output> {
+    let draw = io::stderr().is_terminal();
+    let mut ticks = time::interval_at(Instant::now() + DELAY, TICK);
+    let mut frame = 0;
+    let mut drawn = false;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(future, ctrl_c);
+    let result = loop {
+        tokio::select! {
+            output = &mut future => break Some(output),
+            _ = &mut ctrl_c => break None,
+            _ = ticks.tick(), if draw => {
+                let mut stderr = io::stderr();
+                queue!(
+                    stderr,
+                    MoveToColumn(0),
+                    Clear(ClearType::CurrentLine),
+                    Print(format!("{} {}", FRAMES[frame % FRAMES.len()], label))
+                )
+                .ok();
+                stderr.flush().ok();
+                frame += 1;
+                drawn = true;
+            }
+        }
+    };
+
+    if drawn {
+        let mut stderr = io::stderr();
+        queue!(stderr, MoveToColumn(0), Clear(ClearType::CurrentLine)).ok();
+        stderr.flush().ok();
+    }
+    result
+}
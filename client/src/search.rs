@@ -0,0 +1,187 @@
+use crate::{
+    cache::Cache,
+    commands::{exit, exit_code, Options},
+    output::{self, Output},
+    progress,
+};
+use protocol::{Language, User};
+use sdk::Client;
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+};
+
+/// searches the cached roster. a fresh cache answers without touching the
+/// network; a stale one answers right away, marked as such, and is refreshed
+/// afterwards.
+pub async fn run(
+    client: &Client,
+    options: &Options,
+    query: Option<&str>,
+    languages: &[Language],
+) -> ExitCode {
+    let base_url = client.base_url().as_str();
+    let cache = match Cache::load(base_url) {
+        Some(cache) if options.offline => {
+            if cache.is_stale(options.cache_ttl) {
+                eprintln!("offline: {}", cache.describe_age());
+            }
+            cache
+        }
+        Some(cache) if !cache.is_stale(options.cache_ttl) => cache,
+        Some(mut cache) => {
+            print(options, &search(&cache.users, query, languages));
+            eprintln!("{}, refreshing", cache.describe_age());
+            match progress::spin("refreshing cache", cache.refresh(client)).await {
+                Some(Ok(changed)) => {
+                    save(&cache);
+                    if changed {
+                        eprintln!(
+                            "the roster changed on the server; search again for current results"
+                        );
+                    }
+                }
+                Some(Err(e)) => eprintln!("could not refresh: {}", e),
+                None => {}
+            }
+            return ExitCode::SUCCESS;
+        }
+        None if options.offline => {
+            eprintln!(
+                "no cached roster for {}; run a search once while online",
+                base_url
+            );
+            return ExitCode::from(exit::UNAVAILABLE);
+        }
+        None => {
+            let mut cache = Cache::empty(base_url);
+            match progress::spin("fetching roster", cache.refresh(client)).await {
+                Some(Ok(_)) => save(&cache),
+                Some(Err(e)) => {
+                    eprintln!("error: {}", e);
+                    return exit_code(&e);
+                }
+                None => return ExitCode::from(exit::INTERRUPTED),
+            }
+            cache
+        }
+    };
+
+    print(options, &search(&cache.users, query, languages));
+    ExitCode::SUCCESS
+}
+
+/// users knowing every one of `languages` whose name fuzzy matches `query`,
+/// best match first.
+pub fn search(users: &[User], query: Option<&str>, languages: &[Language]) -> Vec<User> {
+    let mut found: Vec<(i64, &User)> = users
+        .iter()
+        .filter(|user| languages.iter().all(|l| user.languages.contains(l)))
+        .filter_map(|user| match query {
+            Some(query) => fuzzy_score(query, &user.username).map(|score| (score, user)),
+            None => Some((0, user)),
+        })
+        .collect();
+    found.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.username.cmp(&b.1.username)));
+    found.into_iter().map(|(_, user)| user.clone()).collect()
+}
+
+/// `Some` when every character of `query` appears in `candidate` in order,
+/// ignoring case. runs of adjacent matches, matches at the start and
+/// shorter candidates score higher, so `frk` finds `fork` before
+/// `frankenstein_kid`.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut next = 0;
+    let mut last = None;
+    for (i, c) in candidate.iter().enumerate() {
+        if next < query.len() && *c == query[next] {
+            score += 1;
+            if i == 0 {
+                score += 8;
+            }
+            if last.is_some_and(|last| last + 1 == i) {
+                score += 4;
+            }
+            last = Some(i);
+            next += 1;
+        }
+    }
+    if next < query.len() {
+        return None;
+    }
+    if candidate == query {
+        score += 100;
+    }
+    Some(score * 10 - candidate.len() as i64)
+}
+
+fn print(options: &Options, users: &[User]) {
+    let rendered = output::render(options.format, &Output::Users(users.to_vec()));
+    writeln!(io::stdout(), "{}", rendered).ok();
+}
+
+fn save(cache: &Cache) {
+    if let Err(e) = cache.save() {
+        eprintln!("could not save the roster cache: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(username: &str, languages: &[Language]) -> User {
+        User {
+            username: username.to_string(),
+            languages: languages.to_vec(),
+            discord_id: String::new(),
+            deleted_at: None,
+        }
+    }
+
+    fn names(users: &[User]) -> Vec<&str> {
+        users.iter().map(|user| user.username.as_str()).collect()
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_character_in_order() {
+        assert!(fuzzy_score("frk", "fork").is_some());
+        assert!(fuzzy_score("FRK", "Fork").is_some());
+        assert_eq!(fuzzy_score("kfr", "fork"), None);
+        assert_eq!(fuzzy_score("forks", "fork"), None);
+        assert_eq!(fuzzy_score("", "fork"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_tight_early_short_matches() {
+        let score = |candidate| fuzzy_score("frk", candidate).unwrap();
+        assert!(score("fork") > score("frankenstein_kid"));
+        assert!(score("frk") > score("fork"));
+        assert!(score("frkx") > score("xfrk"));
+    }
+
+    #[test]
+    fn search_filters_by_language_and_ranks_by_score() {
+        let users = [
+            user("frankenstein_kid", &[Language::Rust]),
+            user("fork", &[Language::Rust, Language::C]),
+            user("alice", &[Language::Rust]),
+        ];
+        assert_eq!(
+            names(&search(&users, Some("frk"), &[])),
+            ["fork", "frankenstein_kid"]
+        );
+        assert_eq!(names(&search(&users, None, &[Language::C])), ["fork"]);
+        assert_eq!(
+            names(&search(&users, None, &[])),
+            ["alice", "fork", "frankenstein_kid"]
+        );
+    }
+}
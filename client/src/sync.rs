@@ -0,0 +1,359 @@
+use crate::{
+    commands::{confirm, exit, exit_code},
+    progress,
+};
+use crossterm::style::{StyledContent, Stylize};
+use protocol::{CreateUserRequest, Language, User};
+use sdk::Client;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, IsTerminal},
+    path::Path,
+    process::ExitCode,
+};
+
+/// the roster file, e.g.
+///
+/// ```yaml
+/// users:
+///   - username: fork
+///     languages: [rust, c]
+///     discord_id: "123"
+///   - username: alice
+///     languages: [haskell]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Roster {
+    users: Vec<RosterUser>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RosterUser {
+    username: String,
+    #[serde(default)]
+    languages: Vec<String>,
+    discord_id: Option<String>,
+}
+
+/// one change to the server, each a single existing api call.
+pub enum Step {
+    Create(CreateUserRequest),
+    AddLang(String, Vec<Language>),
+    RemoveLang(String, Vec<Language>),
+    Delete(String),
+}
+
+pub struct Plan {
+    pub steps: Vec<Step>,
+    /// differences sync can't fix, like a changed discord id.
+    pub warnings: Vec<String>,
+}
+
+pub async fn run(client: &Client, file: &Path, prune: bool, dry_run: bool, yes: bool) -> ExitCode {
+    let desired = match load(file) {
+        Ok(desired) => desired,
+        Err(e) => {
+            eprintln!("error: {}: {}", file.display(), e);
+            return ExitCode::from(exit::USAGE);
+        }
+    };
+
+    let label = format!("waiting for {}", client.base_url());
+    let current = match progress::spin(&label, client.list_users()).await {
+        Some(Ok(users)) => users,
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            return exit_code(&e);
+        }
+        None => return ExitCode::from(exit::INTERRUPTED),
+    };
+
+    let plan = plan(&desired, &current, prune);
+    for warning in &plan.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    if plan.steps.is_empty() {
+        println!("no changes to apply");
+        return ExitCode::SUCCESS;
+    }
+    print_plan(&plan);
+    if dry_run {
+        return ExitCode::SUCCESS;
+    }
+    if !yes && !confirm("apply these changes?") {
+        return ExitCode::from(exit::USAGE);
+    }
+
+    for (i, step) in plan.steps.iter().enumerate() {
+        match progress::spin(&step.to_string(), apply(client, step)).await {
+            Some(Ok(())) => println!("done: {}", step),
+            Some(Err(e)) => {
+                eprintln!("error: {}: {}", step, e);
+                eprintln!("stopped after {} of {} changes", i, plan.steps.len());
+                return exit_code(&e);
+            }
+            None => {
+                eprintln!("cancelled after {} of {} changes", i, plan.steps.len());
+                return ExitCode::from(exit::INTERRUPTED);
+            }
+        }
+    }
+    println!("applied {} changes", plan.steps.len());
+    ExitCode::SUCCESS
+}
+
+/// reads and checks the roster file, returning users as create requests.
+fn load(file: &Path) -> Result<Vec<CreateUserRequest>, String> {
+    let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let roster: Roster = serde_yaml::from_str(&text).map_err(|e| e.to_string())?;
+
+    let mut seen = HashSet::new();
+    let mut users = Vec::with_capacity(roster.users.len());
+    for user in roster.users {
+        if user.username.trim().is_empty() {
+            return Err("a user has an empty username".to_string());
+        }
+        if !seen.insert(user.username.clone()) {
+            return Err(format!("{} is listed twice", user.username));
+        }
+        let mut languages = Vec::new();
+        for name in &user.languages {
+            let language: Language = name
+                .parse()
+                .map_err(|e| format!("{}: {}", user.username, e))?;
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+        users.push(CreateUserRequest {
+            username: user.username,
+            languages,
+            discord_id: user.discord_id,
+        });
+    }
+    Ok(users)
+}
+
+/// what it takes to turn `current` into `desired`. users missing from the
+/// file are only deleted with `prune`.
+pub fn plan(desired: &[CreateUserRequest], current: &[User], prune: bool) -> Plan {
+    let mut steps = Vec::new();
+    let mut warnings = Vec::new();
+
+    for want in desired {
+        let Some(have) = current.iter().find(|u| u.username == want.username) else {
+            steps.push(Step::Create(want.clone()));
+            continue;
+        };
+
+        let add: Vec<Language> = want
+            .languages
+            .iter()
+            .filter(|l| !have.languages.contains(l))
+            .copied()
+            .collect();
+        let remove: Vec<Language> = have
+            .languages
+            .iter()
+            .filter(|l| !want.languages.contains(l))
+            .copied()
+            .collect();
+        if !add.is_empty() {
+            steps.push(Step::AddLang(want.username.clone(), add));
+        }
+        if !remove.is_empty() {
+            steps.push(Step::RemoveLang(want.username.clone(), remove));
+        }
+
+        if let Some(discord_id) = &want.discord_id {
+            if *discord_id != have.discord_id {
+                warnings.push(format!(
+                    "{} has discord id {:?} on the server but {:?} in the file; sync can't change it",
+                    want.username, have.discord_id, discord_id
+                ));
+            }
+        }
+    }
+
+    let wanted: HashSet<&str> = desired.iter().map(|u| u.username.as_str()).collect();
+    let extra: Vec<&User> = current
+        .iter()
+        .filter(|u| !wanted.contains(u.username.as_str()))
+        .collect();
+    if prune {
+        steps.extend(extra.iter().map(|u| Step::Delete(u.username.clone())));
+    } else if !extra.is_empty() {
+        warnings.push(format!(
+            "{} users on the server aren't in the file; pass --prune to delete them",
+            extra.len()
+        ));
+    }
+
+    Plan { steps, warnings }
+}
+
+fn print_plan(plan: &Plan) {
+    let color = io::stdout().is_terminal();
+    let (mut creates, mut updates, mut deletes) = (0, 0, 0);
+    for step in &plan.steps {
+        let (symbol, tint): (&str, fn(String) -> StyledContent<String>) = match step {
+            Step::Create(_) => {
+                creates += 1;
+                ("+", Stylize::green)
+            }
+            Step::AddLang(..) | Step::RemoveLang(..) => {
+                updates += 1;
+                ("~", Stylize::yellow)
+            }
+            Step::Delete(_) => {
+                deletes += 1;
+                ("-", Stylize::red)
+            }
+        };
+        let line = format!("{} {}", symbol, step);
+        if color {
+            println!("{}", tint(line));
+        } else {
+            println!("{}", line);
+        }
+    }
+    println!(
+        "\n{} changes: {} to create, {} to update, {} to delete",
+        plan.steps.len(),
+        creates,
+        updates,
+        deletes
+    );
+}
+
+async fn apply(client: &Client, step: &Step) -> Result<(), sdk::Error> {
+    match step {
+        Step::Create(request) => client.create_user(request).await.map(drop),
+        Step::AddLang(username, languages) => {
+            client.add_languages(username, languages).await.map(drop)
+        }
+        Step::RemoveLang(username, languages) => {
+            client.remove_languages(username, languages).await.map(drop)
+        }
+        Step::Delete(username) => client.delete_user(username).await,
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Create(request) => {
+                write!(
+                    f,
+                    "create {} [{}]",
+                    request.username,
+                    join(&request.languages)
+                )?;
+                if let Some(discord_id) = &request.discord_id {
+                    write!(f, " discord {}", discord_id)?;
+                }
+                Ok(())
+            }
+            Step::AddLang(username, languages) => {
+                write!(f, "add-lang {} [{}]", username, join(languages))
+            }
+            Step::RemoveLang(username, languages) => {
+                write!(f, "rm-lang {} [{}]", username, join(languages))
+            }
+            Step::Delete(username) => write!(f, "delete {}", username),
+        }
+    }
+}
+
+fn join(languages: &[Language]) -> String {
+    languages
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn want(username: &str, languages: &[Language], discord_id: Option<&str>) -> CreateUserRequest {
+        CreateUserRequest {
+            username: username.to_string(),
+            languages: languages.to_vec(),
+            discord_id: discord_id.map(str::to_string),
+        }
+    }
+
+    fn have(username: &str, languages: &[Language], discord_id: &str) -> User {
+        User {
+            username: username.to_string(),
+            languages: languages.to_vec(),
+            discord_id: discord_id.to_string(),
+            deleted_at: None,
+        }
+    }
+
+    fn steps(plan: &Plan) -> Vec<String> {
+        plan.steps.iter().map(Step::to_string).collect()
+    }
+
+    #[test]
+    fn plan_creates_missing_and_diffs_languages() {
+        let desired = [
+            want("fork", &[Language::Rust, Language::C], None),
+            want("alice", &[Language::Haskell], Some("7")),
+        ];
+        let current = [have("fork", &[Language::C, Language::Go], "1")];
+
+        let plan = plan(&desired, &current, false);
+        assert_eq!(
+            steps(&plan),
+            [
+                "add-lang fork [Rust]",
+                "rm-lang fork [Go]",
+                "create alice [Haskell] discord 7",
+            ]
+        );
+        assert!(plan.warnings.is_empty());
+    }
+
+    #[test]
+    fn plan_is_empty_when_in_sync() {
+        let desired = [want("fork", &[Language::Rust], Some("1"))];
+        let current = [have("fork", &[Language::Rust], "1")];
+
+        let plan = plan(&desired, &current, false);
+        assert!(plan.steps.is_empty());
+        assert!(plan.warnings.is_empty());
+    }
+
+    #[test]
+    fn plan_only_deletes_extra_users_with_prune() {
+        let desired = [want("fork", &[], None)];
+        let current = [have("fork", &[], ""), have("bob", &[], "")];
+
+        let kept = plan(&desired, &current, false);
+        assert!(kept.steps.is_empty());
+        assert_eq!(kept.warnings.len(), 1);
+
+        let pruned = plan(&desired, &current, true);
+        assert_eq!(steps(&pruned), ["delete bob"]);
+        assert!(pruned.warnings.is_empty());
+    }
+
+    #[test]
+    fn plan_warns_about_a_changed_discord_id() {
+        let desired = [want("fork", &[], Some("2"))];
+        let current = [have("fork", &[], "1")];
+
+        let plan = plan(&desired, &current, false);
+        assert!(plan.steps.is_empty());
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.warnings[0].contains("discord id"));
+    }
+}
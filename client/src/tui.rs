@@ -0,0 +1,639 @@
+use crate::{commands::exit_code, output::badge_colors};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Print, PrintStyledContent, Stylize},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use protocol::{Language, User};
+use sdk::Client;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    process::ExitCode,
+};
+
+const SIDEBAR_WIDTH: usize = 22;
+const DETAIL_WIDTH: usize = 34;
+const HELP: &str =
+    " ↑↓ move  tab pane  / search  a add lang  r remove lang  d delete  g refresh  q quit";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    List,
+    Sidebar,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    Add,
+    Remove,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    Search,
+    /// reading a comma separated language list for the selected user.
+    Prompt(PromptKind, String),
+    /// waiting for y to delete this user.
+    Confirm(String),
+}
+
+/// something that needs the server.
+enum Action {
+    Quit,
+    Refresh,
+    Add(String, Vec<Language>),
+    Remove(String, Vec<Language>),
+    Delete(String),
+}
+
+struct App {
+    users: Vec<User>,
+    /// the sidebar: every language on the roster with how many users know it.
+    languages: Vec<(Language, usize)>,
+    search: String,
+    filter: Option<Language>,
+    focus: Focus,
+    mode: Mode,
+    selected: usize,
+    offset: usize,
+    /// 0 is "all", then an index into `languages` plus one.
+    sidebar_selected: usize,
+    sidebar_offset: usize,
+    status: Status,
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+/// raw mode and the alternate screen, undone on drop so a panic or early
+/// return can't leave the terminal broken.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        execute!(io::stdout(), Show, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+pub async fn run(client: &Client) -> ExitCode {
+    let users = match client.list_users().await {
+        Ok(users) => users,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return exit_code(&e);
+        }
+    };
+
+    let mut app = App::new(users);
+    let result = match Screen::enter() {
+        Ok(_screen) => app.run(client).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("terminal error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+impl App {
+    fn new(users: Vec<User>) -> Self {
+        let mut app = App {
+            users: Vec::new(),
+            languages: Vec::new(),
+            search: String::new(),
+            filter: None,
+            focus: Focus::List,
+            mode: Mode::Normal,
+            selected: 0,
+            offset: 0,
+            sidebar_selected: 0,
+            sidebar_offset: 0,
+            status: Status::Info(String::new()),
+        };
+        app.set_users(users);
+        app.status = Status::Info(format!("loaded {} users", app.users.len()));
+        app
+    }
+
+    async fn run(&mut self, client: &Client) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        loop {
+            self.draw(&mut stdout)?;
+            // anything else, like a resize, just redraws
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match self.handle_key(key) {
+                Some(Action::Quit) => return Ok(()),
+                Some(action) => {
+                    self.status = Status::Info("working...".to_string());
+                    self.draw(&mut stdout)?;
+                    self.perform(client, action).await;
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// users that match the search and the language filter.
+    fn visible(&self) -> Vec<&User> {
+        let search = self.search.to_lowercase();
+        self.users
+            .iter()
+            .filter(|user| {
+                user.username.to_lowercase().contains(&search) || user.discord_id.contains(&search)
+            })
+            .filter(|user| self.filter.is_none_or(|l| user.languages.contains(&l)))
+            .collect()
+    }
+
+    fn selected_user(&self) -> Option<&User> {
+        self.visible().get(self.selected).copied()
+    }
+
+    /// replaces the roster, keeping the selected user and filter where they
+    /// still exist.
+    fn set_users(&mut self, mut users: Vec<User>) {
+        let selected = self.selected_user().map(|u| u.username.clone());
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        self.users = users;
+
+        let mut counts: HashMap<Language, usize> = HashMap::new();
+        for user in &self.users {
+            for language in &user.languages {
+                *counts.entry(*language).or_default() += 1;
+            }
+        }
+        let mut languages: Vec<(Language, usize)> = counts.into_iter().collect();
+        languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.to_string().cmp(&b.0.to_string())));
+        self.languages = languages;
+
+        self.sidebar_selected = match self.filter {
+            Some(filter) => match self.languages.iter().position(|(l, _)| *l == filter) {
+                Some(i) => i + 1,
+                None => {
+                    self.filter = None;
+                    0
+                }
+            },
+            None => 0,
+        };
+
+        let visible = self.visible();
+        self.selected = selected
+            .and_then(|name| visible.iter().position(|u| u.username == name))
+            .unwrap_or(0)
+            .min(visible.len().saturating_sub(1));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+
+        match self.mode.clone() {
+            Mode::Normal => self.handle_normal(key),
+            Mode::Search => {
+                match key.code {
+                    KeyCode::Char(c) => {
+                        self.search.push(c);
+                        self.selected = 0;
+                    }
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                        self.selected = 0;
+                    }
+                    KeyCode::Enter => self.mode = Mode::Normal,
+                    KeyCode::Esc => {
+                        self.search.clear();
+                        self.selected = 0;
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Up | KeyCode::Down => self.move_selection(key.code),
+                    _ => {}
+                }
+                None
+            }
+            Mode::Prompt(kind, mut input) => {
+                match key.code {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Esc => {
+                        self.mode = Mode::Normal;
+                        return None;
+                    }
+                    KeyCode::Enter => {
+                        self.mode = Mode::Normal;
+                        return self.prompt_action(kind, &input);
+                    }
+                    _ => {}
+                }
+                self.mode = Mode::Prompt(kind, input);
+                None
+            }
+            Mode::Confirm(username) => {
+                self.mode = Mode::Normal;
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    Some(Action::Delete(username))
+                } else {
+                    self.status = Status::Info("delete cancelled".to_string());
+                    None
+                }
+            }
+        }
+    }
+
+    fn handle_normal(&mut self, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Char('q') => return Some(Action::Quit),
+            KeyCode::Esc if !self.search.is_empty() => {
+                self.search.clear();
+                self.selected = 0;
+            }
+            KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char('/') => {
+                self.focus = Focus::List;
+                self.mode = Mode::Search;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::List => Focus::Sidebar,
+                    Focus::Sidebar => Focus::List,
+                };
+            }
+            KeyCode::Char('g') | KeyCode::F(5) => return Some(Action::Refresh),
+            KeyCode::Char('a') | KeyCode::Char('r') | KeyCode::Char('d') => {
+                let Some(user) = self.selected_user() else {
+                    self.status = Status::Error("no user selected".to_string());
+                    return None;
+                };
+                let username = user.username.clone();
+                self.mode = match key.code {
+                    KeyCode::Char('a') => Mode::Prompt(PromptKind::Add, String::new()),
+                    KeyCode::Char('r') => Mode::Prompt(PromptKind::Remove, String::new()),
+                    _ => Mode::Confirm(username),
+                };
+            }
+            KeyCode::Char('k') => self.move_selection(KeyCode::Up),
+            KeyCode::Char('j') => self.move_selection(KeyCode::Down),
+            code => self.move_selection(code),
+        }
+        None
+    }
+
+    fn move_selection(&mut self, code: KeyCode) {
+        let page = terminal::size()
+            .map(|(_, h)| (h as usize).saturating_sub(5).max(1))
+            .unwrap_or(10);
+        let (current, len) = match self.focus {
+            Focus::List => (self.selected, self.visible().len()),
+            Focus::Sidebar => (self.sidebar_selected, self.languages.len() + 1),
+        };
+        if len == 0 {
+            return;
+        }
+        let next = match code {
+            KeyCode::Up => current.saturating_sub(1),
+            KeyCode::Down => (current + 1).min(len - 1),
+            KeyCode::PageUp => current.saturating_sub(page),
+            KeyCode::PageDown => (current + page).min(len - 1),
+            KeyCode::Home => 0,
+            KeyCode::End => len - 1,
+            _ => return,
+        };
+
+        match self.focus {
+            Focus::List => self.selected = next,
+            // the filter follows the sidebar cursor
+            Focus::Sidebar => {
+                self.sidebar_selected = next;
+                self.filter = next.checked_sub(1).map(|i| self.languages[i].0);
+                self.selected = 0;
+            }
+        }
+    }
+
+    fn prompt_action(&mut self, kind: PromptKind, input: &str) -> Option<Action> {
+        let parsed: Result<Vec<Language>, _> = input
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect();
+        let languages = match parsed {
+            Ok(languages) if languages.is_empty() => return None,
+            Ok(languages) => languages,
+            Err(e) => {
+                self.status = Status::Error(e.to_string());
+                return None;
+            }
+        };
+        let username = self.selected_user()?.username.clone();
+        Some(match kind {
+            PromptKind::Add => Action::Add(username, languages),
+            PromptKind::Remove => Action::Remove(username, languages),
+        })
+    }
+
+    async fn perform(&mut self, client: &Client, action: Action) {
+        let result = match action {
+            Action::Quit => return,
+            Action::Refresh => client.list_users().await.map(|users| {
+                let count = users.len();
+                self.set_users(users);
+                format!("loaded {} users", count)
+            }),
+            Action::Add(username, languages) => client
+                .add_languages(&username, &languages)
+                .await
+                .map(|user| self.replace_user(user)),
+            Action::Remove(username, languages) => client
+                .remove_languages(&username, &languages)
+                .await
+                .map(|user| self.replace_user(user)),
+            Action::Delete(username) => client.delete_user(&username).await.map(|()| {
+                let users = self
+                    .users
+                    .iter()
+                    .filter(|u| u.username != username)
+                    .cloned()
+                    .collect();
+                self.set_users(users);
+                format!("deleted {}", username)
+            }),
+        };
+
+        self.status = match result {
+            Ok(message) => Status::Info(message),
+            Err(e) => Status::Error(e.to_string()),
+        };
+    }
+
+    fn replace_user(&mut self, user: User) -> String {
+        let message = format!("updated {}", user.username);
+        let mut users: Vec<User> = self
+            .users
+            .iter()
+            .filter(|u| u.username != user.username)
+            .cloned()
+            .collect();
+        users.push(user);
+        self.set_users(users);
+        message
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        queue!(out, Clear(ClearType::All))?;
+
+        if width < SIDEBAR_WIDTH + DETAIL_WIDTH + 20 || height < 8 {
+            put(out, 0, 0, width, "terminal too small")?;
+            return out.flush();
+        }
+
+        let list_x = SIDEBAR_WIDTH + 1;
+        let list_width = width - SIDEBAR_WIDTH - DETAIL_WIDTH - 2;
+        let detail_x = list_x + list_width + 1;
+        let body_height = height - 4;
+
+        // title and search
+        let search = match self.mode {
+            Mode::Search => format!("search: {}_", self.search),
+            _ if !self.search.is_empty() => format!("search: {}", self.search),
+            _ => String::new(),
+        };
+        let title = format!(" CCweb roster  {} users   {}", self.users.len(), search);
+        queue!(
+            out,
+            MoveTo(0, 0),
+            PrintStyledContent(fit(&title, width).bold())
+        )?;
+
+        // column headings and separators
+        let visible_count = self.visible().len();
+        put(out, 0, 1, SIDEBAR_WIDTH, " LANGUAGES")?;
+        put(
+            out,
+            list_x,
+            1,
+            list_width,
+            &format!(" USERS ({})", visible_count),
+        )?;
+        put(out, detail_x, 1, DETAIL_WIDTH, " DETAIL")?;
+        for y in 1..height - 2 {
+            queue!(
+                out,
+                MoveTo(SIDEBAR_WIDTH as u16, y as u16),
+                Print('│'),
+                MoveTo((detail_x - 1) as u16, y as u16),
+                Print('│')
+            )?;
+        }
+
+        self.draw_sidebar(out, body_height)?;
+        self.draw_list(out, list_x, list_width, body_height)?;
+        self.draw_detail(out, detail_x, body_height)?;
+
+        // status or prompt, then key help
+        let status_y = height - 2;
+        match &self.mode {
+            Mode::Prompt(kind, input) => {
+                let verb = match kind {
+                    PromptKind::Add => "add languages",
+                    PromptKind::Remove => "remove languages",
+                };
+                put(out, 0, status_y, width, &format!(" {}: {}_", verb, input))?;
+            }
+            Mode::Confirm(username) => {
+                let question = format!(" delete {}? (y/n)", username);
+                queue!(
+                    out,
+                    MoveTo(0, status_y as u16),
+                    PrintStyledContent(fit(&question, width).yellow().bold())
+                )?;
+            }
+            _ => match &self.status {
+                Status::Info(message) => put(out, 0, status_y, width, &format!(" {}", message))?,
+                Status::Error(message) => queue!(
+                    out,
+                    MoveTo(0, status_y as u16),
+                    PrintStyledContent(fit(&format!(" {}", message), width).red())
+                )?,
+            },
+        }
+        queue!(
+            out,
+            MoveTo(0, (height - 1) as u16),
+            PrintStyledContent(fit(HELP, width).dim())
+        )?;
+
+        out.flush()
+    }
+
+    fn draw_sidebar(&mut self, out: &mut impl Write, height: usize) -> io::Result<()> {
+        self.sidebar_offset = scroll(self.sidebar_selected, self.sidebar_offset, height);
+
+        let entries = std::iter::once(format!(" All ({})", self.users.len())).chain(
+            self.languages
+                .iter()
+                .map(|(language, count)| format!(" {} ({})", language, count)),
+        );
+        for (row, (i, entry)) in entries
+            .enumerate()
+            .skip(self.sidebar_offset)
+            .take(height)
+            .enumerate()
+        {
+            let text = fit(&entry, SIDEBAR_WIDTH);
+            let y = (row + 2) as u16;
+            if i == self.sidebar_selected {
+                let styled = if self.focus == Focus::Sidebar {
+                    text.reverse()
+                } else {
+                    text.bold()
+                };
+                queue!(out, MoveTo(0, y), PrintStyledContent(styled))?;
+            } else {
+                queue!(out, MoveTo(0, y), Print(text))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_list(
+        &mut self,
+        out: &mut impl Write,
+        x: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<()> {
+        self.offset = scroll(self.selected, self.offset, height);
+
+        let visible = self.visible();
+        if visible.is_empty() {
+            return put(out, x, 2, width, " no matching users");
+        }
+        let name_width = visible
+            .iter()
+            .map(|u| u.username.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(width / 2);
+        for (row, (i, user)) in visible
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(height)
+            .enumerate()
+        {
+            let languages: Vec<String> = user.languages.iter().map(|l| l.to_string()).collect();
+            let line = format!(
+                " {:<name_width$}  {}",
+                user.username,
+                languages.join(", "),
+                name_width = name_width
+            );
+            let text = fit(&line, width);
+            let y = (row + 2) as u16;
+            if i == self.selected {
+                let styled = if self.focus == Focus::List {
+                    text.reverse()
+                } else {
+                    text.bold()
+                };
+                queue!(out, MoveTo(x as u16, y), PrintStyledContent(styled))?;
+            } else {
+                queue!(out, MoveTo(x as u16, y), Print(text))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_detail(&self, out: &mut impl Write, x: usize, height: usize) -> io::Result<()> {
+        let Some(user) = self.selected_user() else {
+            return Ok(());
+        };
+        let discord = if user.discord_id.is_empty() {
+            "-"
+        } else {
+            &user.discord_id
+        };
+
+        queue!(
+            out,
+            MoveTo(x as u16, 2),
+            PrintStyledContent(fit(&format!(" {}", user.username), DETAIL_WIDTH).bold())
+        )?;
+        put(out, x, 4, DETAIL_WIDTH, &format!(" discord    {}", discord))?;
+        put(
+            out,
+            x,
+            5,
+            DETAIL_WIDTH,
+            &format!(" languages  {}", user.languages.len()),
+        )?;
+        for (row, language) in user
+            .languages
+            .iter()
+            .take(height.saturating_sub(5))
+            .enumerate()
+        {
+            let (bg, fg) = badge_colors(*language);
+            let badge = fit(&format!(" {} ", language), DETAIL_WIDTH - 3);
+            queue!(
+                out,
+                MoveTo((x + 2) as u16, (row + 7) as u16),
+                PrintStyledContent(badge.trim_end().to_string().with(fg).on(bg))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// keeps `selected` inside a window of `height` rows starting at `offset`.
+fn scroll(selected: usize, offset: usize, height: usize) -> usize {
+    if selected < offset {
+        selected
+    } else if selected >= offset + height {
+        selected + 1 - height
+    } else {
+        offset
+    }
+}
+
+fn put(out: &mut impl Write, x: usize, y: usize, width: usize, text: &str) -> io::Result<()> {
+    queue!(out, MoveTo(x as u16, y as u16), Print(fit(text, width)))
+}
+
+/// cuts or pads `text` to exactly `width` columns.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
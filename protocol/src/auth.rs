@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// body of `POST /v1/auth/login`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoginRequest {
+    /// the admin key or a personal token.
+    pub key: String,
+}
+
+/// body of `POST /v1/auth/refresh` and `POST /v1/auth/logout`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// answer to `DELETE /v1/admin/sessions`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionsRevoked {
+    /// how many live sessions were ended.
+    pub revoked: usize,
+}
+
+/// a session: a short-lived access token to send as `Authorization: Bearer`
+/// and a refresh token that gets a new pair once. each refresh token works
+/// exactly once; using a spent one ends the session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub access_token: String,
+    /// always `Bearer`.
+    pub token_type: String,
+    /// seconds until `access_token` expires.
+    pub expires_in: u64,
+    pub refresh_token: String,
+    /// seconds until `refresh_token` expires.
+    pub refresh_expires_in: u64,
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// body of every error response from the `/v1` routes:
+/// `{"error": {"code": "user_not_found", "message": "User not found"}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// stable, machine readable, snake_case.
+    pub code: String,
+    /// for humans; may change between versions.
+    pub message: String,
+}
+
+impl ErrorEnvelope {
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            error: ErrorBody {
+                code: code.into(),
+                message: message.into(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ErrorEnvelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.error.message, self.error.code)
+    }
+}
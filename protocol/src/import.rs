@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// one user as it appears in an import file, before validation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRow {
+    pub username: String,
+    #[serde(default)]
+    pub languages: ImportLanguages,
+    #[serde(default)]
+    pub discord_id: Option<String>,
+}
+
+/// json rows may list languages as an array or as a `RUST|C` string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ImportLanguages {
+    List(Vec<String>),
+    Joined(String),
+}
+
+impl Default for ImportLanguages {
+    fn default() -> Self {
+        ImportLanguages::List(Vec::new())
+    }
+}
+
+/// what to do when an imported username already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Skip,
+    Overwrite,
+    Merge,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// apply nothing unless every row is valid and applies cleanly.
+    #[default]
+    Atomic,
+    /// apply the valid rows and report the rest.
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub mode: ImportMode,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Created,
+    Overwritten,
+    Merged,
+    Skipped,
+    Invalid,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowResult {
+    /// 1-based position in the import, not counting a csv header.
+    pub row: usize,
+    pub username: String,
+    pub status: RowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub mode: ImportMode,
+    pub on_conflict: ConflictPolicy,
+    /// false when an atomic import was rejected and nothing was written.
+    pub applied: bool,
+    pub created: usize,
+    pub overwritten: usize,
+    pub merged: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+    pub failed: usize,
+    pub rows: Vec<RowResult>,
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms, clippy::enum_variant_names)] // variant names are the on-disk format
+pub enum Language {
+    C,
+    CPP,
+    CSharp,
+    Java,
+    JavaScript,
+    TypeScript,
+    Python,
+    Ruby,
+    Rust,
+    Go,
+    Swift,
+    Kotlin,
+    Lua,
+    PHP,
+    Perl,
+    ObjectiveC,
+    Scala,
+    Haskell,
+    Shell,
+    R,
+    Julia,
+    Dart,
+    VB,
+    FSharp,
+    Lisp,
+    Prolog,
+    Assembly,
+    SQL,
+    HTML,
+    CSS,
+    Verilog,
+    Matlab,
+    Cobol,
+    Fortran,
+    Ada,
+    Delphi,
+    Smalltalk,
+    Erlang,
+    Tcl,
+    Scheme,
+    Apex,
+    ApexTrigger,
+    CoffeeScript,
+    Elm,
+    PureScript,
+    Crystal,
+    Elixir,
+    Raku,
+    Hack,
+    VHDL,
+    BadLanguage,
+}
+
+impl Language {
+    /// every real language, in declaration order. `BadLanguage` is left out.
+    pub const ALL: &'static [Language] = &[
+        Language::C,
+        Language::CPP,
+        Language::CSharp,
+        Language::Java,
+        Language::JavaScript,
+        Language::TypeScript,
+        Language::Python,
+        Language::Ruby,
+        Language::Rust,
+        Language::Go,
+        Language::Swift,
+        Language::Kotlin,
+        Language::Lua,
+        Language::PHP,
+        Language::Perl,
+        Language::ObjectiveC,
+        Language::Scala,
+        Language::Haskell,
+        Language::Shell,
+        Language::R,
+        Language::Julia,
+        Language::Dart,
+        Language::VB,
+        Language::FSharp,
+        Language::Lisp,
+        Language::Prolog,
+        Language::Assembly,
+        Language::SQL,
+        Language::HTML,
+        Language::CSS,
+        Language::Verilog,
+        Language::Matlab,
+        Language::Cobol,
+        Language::Fortran,
+        Language::Ada,
+        Language::Delphi,
+        Language::Smalltalk,
+        Language::Erlang,
+        Language::Tcl,
+        Language::Scheme,
+        Language::Apex,
+        Language::ApexTrigger,
+        Language::CoffeeScript,
+        Language::Elm,
+        Language::PureScript,
+        Language::Crystal,
+        Language::Elixir,
+        Language::Raku,
+        Language::Hack,
+        Language::VHDL,
+    ];
+}
+
+impl fmt::Display for Language {
+    /// the canonical name, which is also how languages are stored and sent
+    /// in paths: `Rust`, `CPP`, `CSharp`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Language {
+    type Err = ParseLanguageError;
+
+    fn from_str(input: &str) -> Result<Language, Self::Err> {
+        let lowercased_input = input.trim().to_lowercase();
+        match lowercased_input.as_str() {
+            "c" => Ok(Language::C),
+            "cpp" => Ok(Language::CPP),
+            "csharp" | "c#" => Ok(Language::CSharp),
+            "java" => Ok(Language::Java),
+            "javascript" | "js" => Ok(Language::JavaScript),
+            "typescript" | "ts" => Ok(Language::TypeScript),
+            "python" | "py" => Ok(Language::Python),
+            "ruby" => Ok(Language::Ruby),
+            "rust" => Ok(Language::Rust),
+            "go" | "golang" => Ok(Language::Go),
+            "swift" => Ok(Language::Swift),
+            "kotlin" => Ok(Language::Kotlin),
+            "lua" => Ok(Language::Lua),
+            "php" => Ok(Language::PHP),
+            "perl" => Ok(Language::Perl),
+            "objc" | "objective-c" | "objectivec" => Ok(Language::ObjectiveC),
+            "scala" => Ok(Language::Scala),
+            "haskell" => Ok(Language::Haskell),
+            "shell" | "bash" => Ok(Language::Shell),
+            "r" => Ok(Language::R),
+            "julia" => Ok(Language::Julia),
+            "dart" => Ok(Language::Dart),
+            "vb" | "visualbasic" => Ok(Language::VB),
+            "fsharp" => Ok(Language::FSharp),
+            "lisp" => Ok(Language::Lisp),
+            "prolog" => Ok(Language::Prolog),
+            "assembly" => Ok(Language::Assembly),
+            "sql" => Ok(Language::SQL),
+            "html" => Ok(Language::HTML),
+            "css" => Ok(Language::CSS),
+            "verilog" => Ok(Language::Verilog),
+            "vhdl" => Ok(Language::VHDL),
+            "matlab" => Ok(Language::Matlab),
+            "cobol" => Ok(Language::Cobol),
+            "fortran" => Ok(Language::Fortran),
+            "ada" => Ok(Language::Ada),
+            "delphi" => Ok(Language::Delphi),
+            "smalltalk" => Ok(Language::Smalltalk),
+            "erlang" => Ok(Language::Erlang),
+            "tcl" => Ok(Language::Tcl),
+            "scheme" => Ok(Language::Scheme),
+            "apex" => Ok(Language::Apex),
+            "apextrigger" => Ok(Language::ApexTrigger),
+            "coffeescript" => Ok(Language::CoffeeScript),
+            "elm" => Ok(Language::Elm),
+            "purescript" => Ok(Language::PureScript),
+            "crystal" => Ok(Language::Crystal),
+            "elixir" => Ok(Language::Elixir),
+            "raku" => Ok(Language::Raku),
+            "hack" => Ok(Language::Hack),
+            _ => Err(ParseLanguageError(input.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseLanguageError(pub String);
+
+impl fmt::Display for ParseLanguageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown language {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseLanguageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_parses_back_for_every_language() {
+        for language in Language::ALL {
+            assert_eq!(language.to_string().parse(), Ok(*language));
+        }
+    }
+
+    #[test]
+    fn objective_c_spellings() {
+        assert_eq!(Language::ObjectiveC.to_string(), "ObjectiveC");
+        for input in ["objectivec", "ObjectiveC", "objective-c", "objc", " OBJC "] {
+            assert_eq!(input.parse(), Ok(Language::ObjectiveC), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn unknown_language_is_an_error() {
+        assert_eq!(
+            "brainfuck".parse::<Language>(),
+            Err(ParseLanguageError("brainfuck".to_string()))
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! types shared by the server and the client. everything that crosses the
+//! wire lives here, so a protocol change has to compile on both sides.
+
+mod auth;
+mod backup;
+mod error;
+mod export;
+mod import;
+mod language;
+mod mode;
+mod registration;
+mod user;
+
+pub use auth::{LoginRequest, RefreshRequest, SessionResponse, SessionsRevoked};
+pub use backup::{
+    BackupInfo, BackupResponse, Manifest, ManifestFile, RestoreRequest, RestoreResponse,
+};
+pub use error::{ErrorBody, ErrorEnvelope};
+pub use export::ExportFormat;
+pub use import::{
+    ConflictPolicy, ImportLanguages, ImportMode, ImportOptions, ImportReport, ImportRow, RowResult,
+    RowStatus,
+};
+pub use language::{Language, ParseLanguageError};
+pub use mode::{CommandMode, ParseCommandModeError};
+pub use registration::{
+    CreateInviteRequest, Invite, RegisterRequest, RegisterResponse, Registration,
+    RegistrationStatus,
+};
+pub use user::{CreateUserRequest, LanguagesRequest, TokenResponse, User, UserVersion};
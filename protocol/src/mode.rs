@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// the `:mode` segment of the legacy `/:key/:mode/...` routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandMode {
+    Create,
+    Destroy,
+    AppendLanguage,
+    RemoveLanguage,
+}
+
+impl fmt::Display for CommandMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segment = match self {
+            CommandMode::Create => "c",
+            CommandMode::Destroy => "d",
+            CommandMode::AppendLanguage => "a",
+            CommandMode::RemoveLanguage => "r",
+        };
+        write!(f, "{}", segment)
+    }
+}
+
+impl FromStr for CommandMode {
+    type Err = ParseCommandModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "c" => Ok(CommandMode::Create),
+            "d" => Ok(CommandMode::Destroy),
+            "a" => Ok(CommandMode::AppendLanguage),
+            "r" => Ok(CommandMode::RemoveLanguage),
+            _ => Err(ParseCommandModeError),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseCommandModeError;
+
+impl fmt::Display for ParseCommandModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid command mode")
+    }
+}
+
+impl std::error::Error for ParseCommandModeError {}
@@ -0,0 +1,60 @@
+use crate::{Language, User};
+use serde::{Deserialize, Serialize};
+
+/// body of `POST /v1/register`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    #[serde(default)]
+    pub languages: Vec<Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord_id: Option<String>,
+    /// an invite code, which skips approval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationStatus {
+    /// waiting in the queue for an admin.
+    Pending,
+    /// created, through an invite code.
+    Approved,
+}
+
+/// answer to `POST /v1/register`: 202 while pending, 201 once created.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisterResponse {
+    pub status: RegistrationStatus,
+    pub user: User,
+}
+
+/// a registration in the approval queue. its user doesn't exist yet, so it
+/// is in no lookup, listing or export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Registration {
+    pub user: User,
+    /// unix timestamp.
+    pub requested_at: u64,
+}
+
+/// body of `POST /v1/admin/invites`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreateInviteRequest {
+    /// how many registrations the code admits [default: 1]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uses: Option<u32>,
+    /// seconds until the code stops working [default: never]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    pub uses_left: u32,
+    /// unix timestamp; none for a code that never expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
@@ -0,0 +1,48 @@
+use crate::Language;
+use serde::{Deserialize, Serialize};
+
+/// a user as the api returns it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    pub languages: Vec<Language>,
+    pub discord_id: String,
+    /// unix timestamp of a soft delete; only set on records from history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+}
+
+/// an archived copy of a user record. a version is in effect until
+/// `superseded_at`; the live record has no `superseded_at`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserVersion {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_at: Option<u64>,
+    pub user: User,
+}
+
+/// body of `POST /v1/users`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    #[serde(default)]
+    pub languages: Vec<Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord_id: Option<String>,
+}
+
+/// body of `POST` and `DELETE /v1/users/{name}/languages`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguagesRequest {
+    pub languages: Vec<Language>,
+}
+
+/// answer to `POST /v1/users/{name}/token`. the token is shown only here; the
+/// server keeps just its hash. any earlier token stops working, and every
+/// session started with it ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub username: String,
+    pub token: String,
+}
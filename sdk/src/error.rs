@@ -0,0 +1,107 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the base url couldn't be parsed or can't have paths joined onto it.
+    InvalidUrl(String),
+    /// the ca bundle given to the builder couldn't be parsed.
+    InvalidCertificate(String),
+    /// the server answered with an error status. `code` comes from the
+    /// error envelope, e.g. `user_not_found`.
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    /// the request or response body timed out.
+    Timeout,
+    /// the server couldn't be reached.
+    Connect(reqwest::Error),
+    /// any other transport failure.
+    Http(reqwest::Error),
+    /// the response wasn't the json we expected.
+    Decode(serde_json::Error),
+}
+
+impl Error {
+    /// true for an api error with the given code.
+    pub fn is_code(&self, code: &str) -> bool {
+        matches!(self, Error::Api { code: c, .. } if c == code)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::Api { status: 404, .. })
+    }
+
+    /// true when trying again later might work: the server was unreachable,
+    /// slow, or a proxy in front of it gave up.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::Timeout
+                | Error::Connect(_)
+                | Error::Api {
+                    status: 502..=504,
+                    ..
+                }
+        )
+    }
+}
+
+/// the innermost error, which for a failed connection says why, e.g.
+/// "Connection refused (os error 111)" or a dns failure.
+pub(crate) fn root_cause(error: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = error;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(url) => write!(f, "invalid server url {}", url),
+            Error::InvalidCertificate(e) => write!(f, "invalid ca bundle: {}", e),
+            Error::Api {
+                status,
+                code,
+                message,
+            } => write!(f, "{} ({}, HTTP {})", message, code, status),
+            Error::Timeout => write!(f, "request timed out"),
+            Error::Connect(e) => match e.url() {
+                Some(url) => write!(
+                    f,
+                    "could not connect to {}: {}",
+                    url.origin().ascii_serialization(),
+                    root_cause(e)
+                ),
+                None => write!(f, "could not connect to server: {}", root_cause(e)),
+            },
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Decode(e) => write!(f, "unexpected response from server: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connect(e) | Error::Http(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Error::Timeout
+        } else if error.is_connect() {
+            Error::Connect(error)
+        } else {
+            Error::Http(error)
+        }
+    }
+}
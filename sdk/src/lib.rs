@@ -0,0 +1,460 @@
+//! async client for the CCweb `/v1` api.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), sdk::Error> {
+//! let client = sdk::Client::builder("http://localhost:3000")
+//!     .api_key("secret")
+//!     .build()?;
+//! let user = client.get_user("fork").await?;
+//! println!("{} knows {:?}", user.username, user.languages);
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+
+use error::root_cause;
+pub use error::Error;
+pub use protocol;
+pub use protocol::{
+    CreateInviteRequest, CreateUserRequest, Invite, Language, RegisterRequest, RegisterResponse,
+    Registration, RegistrationStatus, SessionResponse, SessionsRevoked, TokenResponse, User,
+    UserVersion,
+};
+pub use reqwest::Url;
+
+use protocol::{ErrorEnvelope, LanguagesRequest, LoginRequest, RefreshRequest};
+use reqwest::{Certificate, Method, Request, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// how requests authenticate.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    /// sent as `X-Api-Key`.
+    ApiKey(String),
+    /// sent as `Authorization: Bearer`.
+    Bearer(String),
+}
+
+/// the roster with the `ETag` the server sent for it.
+#[derive(Debug, Clone)]
+pub struct UserList {
+    pub users: Vec<User>,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    auth: Option<Auth>,
+    retries: u32,
+    backoff: Duration,
+}
+
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_url: String,
+    auth: Option<Auth>,
+    timeout: Duration,
+    connect_timeout: Duration,
+    user_agent: String,
+    retries: u32,
+    backoff: Duration,
+    ca_bundle: Option<Vec<u8>>,
+}
+
+impl ClientBuilder {
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.auth = Some(Auth::ApiKey(key.into()));
+        self
+    }
+
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// limit for a whole request, including reading the body.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// how many times a GET is retried after a timeout, a failed connection
+    /// or a 502/503/504. other requests are never retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// the wait before the first retry; it doubles for each one after.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// trusts the certificates in this pem bundle as well as the system's,
+    /// for servers with a self-signed or private ca certificate.
+    pub fn ca_bundle(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.ca_bundle = Some(pem.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let mut base_url = Url::parse(&self.base_url)
+            .map_err(|e| Error::InvalidUrl(format!("{}: {}", self.base_url, e)))?;
+        if base_url.cannot_be_a_base() {
+            return Err(Error::InvalidUrl(self.base_url));
+        }
+        // so joining `v1/...` keeps any path prefix the server is mounted under
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+
+        let mut http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .user_agent(self.user_agent);
+        if let Some(pem) = &self.ca_bundle {
+            let certificates = Certificate::from_pem_bundle(pem)
+                .map_err(|e| Error::InvalidCertificate(root_cause(&e)))?;
+            if certificates.is_empty() {
+                return Err(Error::InvalidCertificate(
+                    "no certificates in the ca bundle".to_string(),
+                ));
+            }
+            for certificate in certificates {
+                http = http.add_root_certificate(certificate);
+            }
+        }
+        let http = http.build()?;
+
+        Ok(Client {
+            http,
+            base_url,
+            auth: self.auth,
+            retries: self.retries,
+            backoff: self.backoff,
+        })
+    }
+}
+
+impl Client {
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            auth: None,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            user_agent: format!("ccweb-sdk/{}", env!("CARGO_PKG_VERSION")),
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            ca_bundle: None,
+        }
+    }
+
+    /// shorthand for a client with an api key and default timeouts.
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>) -> Result<Self, Error> {
+        Self::builder(base_url).api_key(api_key).build()
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>, Error> {
+        self.json(self.request(Method::GET, &["v1", "users"])).await
+    }
+
+    /// lists users unless they still match `etag`, in which case the server
+    /// answers 304 and this returns `None`.
+    pub async fn list_users_since(&self, etag: Option<&str>) -> Result<Option<UserList>, Error> {
+        let mut request = self.request(Method::GET, &["v1", "users"]);
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        let response = self.send(request).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = response.bytes().await?;
+        let users = serde_json::from_slice(&body).map_err(Error::Decode)?;
+        Ok(Some(UserList { users, etag }))
+    }
+
+    pub async fn get_user(&self, username: &str) -> Result<User, Error> {
+        self.json(self.request(Method::GET, &["v1", "users", username]))
+            .await
+    }
+
+    pub async fn create_user(&self, request: &CreateUserRequest) -> Result<User, Error> {
+        self.json(self.request(Method::POST, &["v1", "users"]).json(request))
+            .await
+    }
+
+    pub async fn add_languages(
+        &self,
+        username: &str,
+        languages: &[Language],
+    ) -> Result<User, Error> {
+        let body = LanguagesRequest {
+            languages: languages.to_vec(),
+        };
+        self.json(
+            self.request(Method::POST, &["v1", "users", username, "languages"])
+                .json(&body),
+        )
+        .await
+    }
+
+    pub async fn remove_languages(
+        &self,
+        username: &str,
+        languages: &[Language],
+    ) -> Result<User, Error> {
+        let body = LanguagesRequest {
+            languages: languages.to_vec(),
+        };
+        self.json(
+            self.request(Method::DELETE, &["v1", "users", username, "languages"])
+                .json(&body),
+        )
+        .await
+    }
+
+    /// soft deletes a user; it can be brought back with [`Client::restore_user`].
+    pub async fn delete_user(&self, username: &str) -> Result<(), Error> {
+        self.send(self.request(Method::DELETE, &["v1", "users", username]))
+            .await
+            .map(drop)
+    }
+
+    pub async fn restore_user(&self, username: &str) -> Result<User, Error> {
+        self.json(self.request(Method::POST, &["v1", "users", username, "restore"]))
+            .await
+    }
+
+    pub async fn history(&self, username: &str) -> Result<Vec<UserVersion>, Error> {
+        self.json(self.request(Method::GET, &["v1", "users", username, "history"]))
+            .await
+    }
+
+    pub async fn revert(&self, username: &str, version: u32) -> Result<User, Error> {
+        let version = version.to_string();
+        self.json(self.request(Method::POST, &["v1", "users", username, "revert", &version]))
+            .await
+    }
+
+    /// issues a personal token for a user, replacing any earlier one. needs
+    /// the admin key. the token is only ever returned here.
+    pub async fn issue_token(&self, username: &str) -> Result<TokenResponse, Error> {
+        self.json(self.request(Method::POST, &["v1", "users", username, "token"]))
+            .await
+    }
+
+    /// revokes a user's personal token, with the admin key or that token.
+    pub async fn revoke_token(&self, username: &str) -> Result<(), Error> {
+        self.send(self.request(Method::DELETE, &["v1", "users", username, "token"]))
+            .await
+            .map(drop)
+    }
+
+    /// starts a session with the admin key or a personal token. send the
+    /// access token with [`Auth::Bearer`] and trade the refresh token with
+    /// [`Client::refresh`] before it expires.
+    pub async fn login(&self, key: &str) -> Result<SessionResponse, Error> {
+        let body = LoginRequest {
+            key: key.to_string(),
+        };
+        self.json(
+            self.request(Method::POST, &["v1", "auth", "login"])
+                .json(&body),
+        )
+        .await
+    }
+
+    /// a new access token and refresh token for a refresh token, which
+    /// stops working.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<SessionResponse, Error> {
+        let body = RefreshRequest {
+            refresh_token: refresh_token.to_string(),
+        };
+        self.json(
+            self.request(Method::POST, &["v1", "auth", "refresh"])
+                .json(&body),
+        )
+        .await
+    }
+
+    /// ends the session the refresh token belongs to.
+    pub async fn logout(&self, refresh_token: &str) -> Result<(), Error> {
+        let body = RefreshRequest {
+            refresh_token: refresh_token.to_string(),
+        };
+        self.send(
+            self.request(Method::POST, &["v1", "auth", "logout"])
+                .json(&body),
+        )
+        .await
+        .map(drop)
+    }
+
+    /// ends every session on the server. needs the admin key.
+    pub async fn revoke_sessions(&self) -> Result<SessionsRevoked, Error> {
+        self.json(self.request(Method::DELETE, &["v1", "admin", "sessions"]))
+            .await
+    }
+
+    /// registers without the admin key. comes back
+    /// [`RegistrationStatus::Pending`] until an admin approves, or
+    /// [`RegistrationStatus::Approved`] when `request` carries a valid
+    /// invite code.
+    pub async fn register(&self, request: &RegisterRequest) -> Result<RegisterResponse, Error> {
+        self.json(
+            self.request(Method::POST, &["v1", "register"])
+                .json(request),
+        )
+        .await
+    }
+
+    /// the approval queue, oldest first.
+    pub async fn registrations(&self) -> Result<Vec<Registration>, Error> {
+        self.json(self.request(Method::GET, &["v1", "admin", "registrations"]))
+            .await
+    }
+
+    pub async fn approve_registration(&self, username: &str) -> Result<User, Error> {
+        self.json(self.request(
+            Method::POST,
+            &["v1", "admin", "registrations", username, "approve"],
+        ))
+        .await
+    }
+
+    pub async fn reject_registration(&self, username: &str) -> Result<(), Error> {
+        self.send(self.request(
+            Method::POST,
+            &["v1", "admin", "registrations", username, "reject"],
+        ))
+        .await
+        .map(drop)
+    }
+
+    pub async fn create_invite(&self, request: &CreateInviteRequest) -> Result<Invite, Error> {
+        self.json(
+            self.request(Method::POST, &["v1", "admin", "invites"])
+                .json(request),
+        )
+        .await
+    }
+
+    /// invites that can still be used.
+    pub async fn invites(&self) -> Result<Vec<Invite>, Error> {
+        self.json(self.request(Method::GET, &["v1", "admin", "invites"]))
+            .await
+    }
+
+    pub async fn revoke_invite(&self, code: &str) -> Result<(), Error> {
+        self.send(self.request(Method::DELETE, &["v1", "admin", "invites", code]))
+            .await
+            .map(drop)
+    }
+
+    /// builds a request to `base_url` plus `segments`, each percent-encoded.
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("base url was checked in build")
+            .pop_if_empty()
+            .extend(segments);
+
+        let request = self.http.request(method, url);
+        match &self.auth {
+            Some(Auth::ApiKey(key)) => request.header("x-api-key", key),
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// sends the request, retrying idempotent ones with exponential backoff.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let mut request = request.build()?;
+        // a delete whose response was lost has already happened, so trying
+        // it again would come back as a 404
+        let idempotent = request.method().is_idempotent() && request.method() != Method::DELETE;
+        let mut attempt = 0;
+        loop {
+            let next = if idempotent && attempt < self.retries {
+                request.try_clone()
+            } else {
+                None
+            };
+            match (self.execute(request).await, next) {
+                (Err(e), Some(next)) if e.is_retryable() => {
+                    let delay = self.backoff.saturating_mul(1 << attempt.min(16));
+                    tokio::time::sleep(delay.min(MAX_BACKOFF)).await;
+                    attempt += 1;
+                    request = next;
+                }
+                (result, _) => return result,
+            }
+        }
+    }
+
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        // 304 only ever answers a conditional request, which expects it
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(match serde_json::from_str::<ErrorEnvelope>(&body) {
+            Ok(envelope) => Error::Api {
+                status: status.as_u16(),
+                code: envelope.error.code,
+                message: envelope.error.message,
+            },
+            Err(_) => Error::Api {
+                status: status.as_u16(),
+                code: "unexpected_response".to_string(),
+                message: if body.is_empty() {
+                    status.to_string()
+                } else {
+                    body
+                },
+            },
+        })
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let body = self.send(request).await?.bytes().await?;
+        serde_json::from_slice(&body).map_err(Error::Decode)
+    }
+}
@@ -0,0 +1,840 @@
+use crate::backup::{self, BackupError};
+use crate::config::{Config, RegistrationMode};
+use crate::export;
+use crate::import;
+use crate::registration;
+use crate::server::{key_matches, FILEPATH};
+use crate::session::{self, RefreshError};
+use crate::token;
+use crate::user::{self, DatabaseError, User};
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{ConnectInfo, FromRequestParts, Path, Query},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::stream::{self, StreamExt};
+use protocol::{
+    BackupInfo, BackupResponse, CreateInviteRequest, CreateUserRequest, ErrorEnvelope,
+    ExportFormat, ImportOptions, Invite, LanguagesRequest, LoginRequest, RefreshRequest,
+    RegisterRequest, RegisterResponse, Registration, RegistrationStatus, RestoreRequest,
+    RestoreResponse, SessionResponse, SessionsRevoked, TokenResponse, UserVersion,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// error envelope for the `/v1` routes: `{"error": {"code": .., "message": ..}}`
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorEnvelope::new(self.code, self.message);
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(error: DatabaseError) -> Self {
+        let (status, code) = match error {
+            DatabaseError::MissingUsername => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "missing_username")
+            }
+            DatabaseError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            DatabaseError::UserAlreadyExists => (StatusCode::CONFLICT, "user_already_exists"),
+            DatabaseError::UserNotDeleted => (StatusCode::CONFLICT, "user_not_deleted"),
+            DatabaseError::VersionNotFound => (StatusCode::NOT_FOUND, "version_not_found"),
+            DatabaseError::FieldTooLong { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "field_too_long")
+            }
+            DatabaseError::ReservedCharacter { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "reserved_character")
+            }
+            DatabaseError::TooManyLanguages { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "too_many_languages")
+            }
+            DatabaseError::TooManyUsers { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "too_many_users")
+            }
+            DatabaseError::RegistrationNotFound => {
+                (StatusCode::NOT_FOUND, "registration_not_found")
+            }
+            DatabaseError::QueueFull { .. } => (StatusCode::SERVICE_UNAVAILABLE, "queue_full"),
+            DatabaseError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
+        };
+        if let DatabaseError::IoError(e) = &error {
+            tracing::error!("storage error: {}", e);
+        }
+        ApiError::new(status, code, error.to_string())
+    }
+}
+
+impl From<BackupError> for ApiError {
+    fn from(error: BackupError) -> Self {
+        let (status, code) = match error {
+            BackupError::NotFound(_) => (StatusCode::NOT_FOUND, "backup_not_found"),
+            BackupError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_backup"),
+            BackupError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
+        };
+        if let BackupError::IoError(e) = &error {
+            tracing::error!("backup error: {}", e);
+        }
+        ApiError::new(status, code, error.to_string())
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        DatabaseError::IoError(error).into()
+    }
+}
+
+/// the `/v1` routes take the key from an `X-Api-Key` or
+/// `Authorization: Bearer` header instead of the path.
+pub fn header_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+}
+
+/// who a request acts as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caller {
+    /// the `API_KEY`: full control.
+    Admin,
+    /// a personal token: reads anything, changes only its own languages.
+    Member(String),
+}
+
+impl Caller {
+    /// the name rate limits and logs know the caller by; never a secret.
+    pub fn name(&self) -> String {
+        match self {
+            Caller::Admin => "API_KEY".to_string(),
+            Caller::Member(username) => format!("user:{}", username),
+        }
+    }
+
+    fn require_admin(&self) -> Result<(), ApiError> {
+        match self {
+            Caller::Admin => Ok(()),
+            Caller::Member(_) => Err(forbidden("This needs the admin key")),
+        }
+    }
+
+    fn require_self_or_admin(&self, username: &str) -> Result<(), ApiError> {
+        match self {
+            Caller::Member(owner) if owner != username => {
+                Err(forbidden("A personal token can only change its own user"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn forbidden(message: &str) -> ApiError {
+    ApiError::new(StatusCode::FORBIDDEN, "forbidden", message)
+}
+
+impl Caller {
+    /// the inverse of [`Caller::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "API_KEY" => Some(Caller::Admin),
+            _ => name
+                .strip_prefix("user:")
+                .map(|username| Caller::Member(username.to_string())),
+        }
+    }
+}
+
+/// resolves a presented key, an access token or a personal token to who it
+/// stands for. `Ok(None)` means it is none of them.
+pub fn identify(key: &str) -> Result<Option<Caller>, DatabaseError> {
+    if key.starts_with(session::ACCESS_PREFIX) {
+        return Ok(session::verify(key));
+    }
+    if key.starts_with(token::PREFIX) {
+        let _guard = user::read_lock();
+        return Ok(token::owner(FILEPATH, key)?.map(Caller::Member));
+    }
+    Ok(key_matches(key).then_some(Caller::Admin))
+}
+
+/// the caller behind the request's key, or a 401.
+fn authorize(headers: &HeaderMap) -> Result<Caller, ApiError> {
+    let Some(key) = header_key(headers) else {
+        crate::metrics::auth_failure("none");
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "missing_api_key",
+            "Missing API key",
+        ));
+    };
+    let (kind, code, message) = if key.starts_with(session::ACCESS_PREFIX) {
+        (
+            "session",
+            "invalid_access_token",
+            "Invalid or expired access token",
+        )
+    } else if key.starts_with(token::PREFIX) {
+        ("token", "invalid_token", "Invalid personal token")
+    } else {
+        ("API_KEY", "invalid_api_key", "Invalid API key")
+    };
+    match identify(key)? {
+        Some(caller) => Ok(caller),
+        None => {
+            crate::metrics::auth_failure(kind);
+            Err(ApiError::new(StatusCode::UNAUTHORIZED, code, message))
+        }
+    }
+}
+
+/// every `/v1` handler takes a `Caller`, so a request without a valid key,
+/// token or session never reaches one.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, ApiError> {
+        authorize(&parts.headers)
+    }
+}
+
+/// `GET /v1/users`
+///
+/// carries an `ETag` of the body, and answers `If-None-Match` with a 304 when
+/// the roster hasn't changed, so clients can keep a cached copy cheaply.
+pub async fn list_users_handler(_caller: Caller, headers: HeaderMap) -> Result<Response, ApiError> {
+    let users = {
+        let _guard = user::read_lock();
+        User::all(FILEPATH)?
+    };
+    let users: Vec<protocol::User> = users.into_iter().map(Into::into).collect();
+    let body = serde_json::to_vec(&users).expect("users always serialize");
+    let etag = format!("\"{:x}\"", Sha256::digest(&body));
+
+    let cached = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*");
+    if cached {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// `POST /v1/users`
+pub async fn create_user_handler(
+    caller: Caller,
+    Json(request): Json<CreateUserRequest>,
+) -> Result<(StatusCode, Json<protocol::User>), ApiError> {
+    caller.require_admin()?;
+
+    let user = User::create_user(
+        Some(request.username),
+        Some(request.languages),
+        request.discord_id,
+    )?;
+    {
+        let _guard = user::write_lock();
+        user.save_to_csv(FILEPATH)?;
+    }
+    tracing::info!(user = %user.username, "created user");
+    Ok((StatusCode::CREATED, Json(user.into())))
+}
+
+/// `GET /v1/users/{name}`
+pub async fn get_user_handler(
+    _caller: Caller,
+    Path(username): Path<String>,
+) -> Result<Json<protocol::User>, ApiError> {
+    let _guard = user::read_lock();
+    Ok(Json(User::lookup_user(FILEPATH, &username)?.into()))
+}
+
+/// `DELETE /v1/users/{name}` soft deletes; see the restore route.
+pub async fn delete_user_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::write_lock();
+    User::remove_user(FILEPATH, &username)?;
+    tracing::info!(user = %username, "deleted user");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /v1/users/{name}/restore`
+pub async fn restore_user_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<Json<protocol::User>, ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::write_lock();
+    let user = User::restore_user(FILEPATH, &username)?;
+    tracing::info!(user = %username, "restored user");
+    Ok(Json(user.into()))
+}
+
+/// `POST /v1/users/{name}/languages`
+pub async fn add_languages_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+    Json(request): Json<LanguagesRequest>,
+) -> Result<Json<protocol::User>, ApiError> {
+    caller.require_self_or_admin(&username)?;
+
+    let _guard = user::write_lock();
+    let mut user = User::lookup_user(FILEPATH, &username)?;
+    user.add_language(request.languages, FILEPATH)?;
+    Ok(Json(user.into()))
+}
+
+/// `DELETE /v1/users/{name}/languages`
+pub async fn remove_languages_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+    Json(request): Json<LanguagesRequest>,
+) -> Result<Json<protocol::User>, ApiError> {
+    caller.require_self_or_admin(&username)?;
+
+    let _guard = user::write_lock();
+    let mut user = User::lookup_user(FILEPATH, &username)?;
+    user.remove_language(request.languages, FILEPATH)?;
+    Ok(Json(user.into()))
+}
+
+/// `POST /v1/users/{name}/token` issues a personal token for the user,
+/// replacing any earlier one and ending the sessions started with it. the
+/// token is only ever shown here.
+pub async fn issue_token_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<(StatusCode, Json<TokenResponse>), ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::write_lock();
+    User::lookup_user(FILEPATH, &username)?;
+    let token = token::issue(FILEPATH, &username)?;
+    // a reissue is how a leaked token is replaced, so what it opened goes too
+    session::revoke_all(&Caller::Member(username.clone()))?;
+    tracing::info!(user = %username, "issued personal token");
+    Ok((StatusCode::CREATED, Json(TokenResponse { username, token })))
+}
+
+/// `DELETE /v1/users/{name}/token`, by the admin or the token's own holder.
+pub async fn revoke_token_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_self_or_admin(&username)?;
+
+    let _guard = user::write_lock();
+    if !token::revoke(FILEPATH, std::slice::from_ref(&username))? {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "token_not_found",
+            format!("{} has no personal token", username),
+        ));
+    }
+    // sessions started with the token go with it
+    session::revoke_all(&Caller::Member(username.clone()))?;
+    tracing::info!(user = %username, "revoked personal token");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /v1/auth/login` trades the admin key or a personal token for a
+/// session. a wrong key counts towards the address's lockout like one sent
+/// in a header.
+pub async fn login_handler(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<LoginRequest>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let Some(caller) = identify(&request.key)?.filter(|_| {
+        // an access token can't be traded for a session of its own
+        !request.key.starts_with(session::ACCESS_PREFIX)
+    }) else {
+        crate::metrics::auth_failure("login");
+        crate::limit::record_auth_failure(addr.ip());
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_credentials",
+            "Invalid API key or personal token",
+        ));
+    };
+    let response = session::login(&caller)?;
+    tracing::info!(caller = %caller.name(), "logged in");
+    Ok(Json(response))
+}
+
+/// `POST /v1/auth/refresh` rotates the refresh token: the one sent stops
+/// working and a new one comes back with a new access token.
+pub async fn refresh_handler(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    match session::refresh(&request.refresh_token) {
+        Ok(response) => Ok(Json(response)),
+        Err(RefreshError::IoError(e)) => Err(e.into()),
+        Err(e) => {
+            crate::metrics::auth_failure("refresh");
+            crate::limit::record_auth_failure(addr.ip());
+            let message = match e {
+                RefreshError::Reused => "Refresh token was already used; the session is revoked",
+                _ => "Invalid or expired refresh token",
+            };
+            Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_refresh_token",
+                message,
+            ))
+        }
+    }
+}
+
+/// `POST /v1/auth/logout` revokes the session, so neither its refresh
+/// token nor any access token issued in it works again.
+pub async fn logout_handler(Json(request): Json<RefreshRequest>) -> Result<StatusCode, ApiError> {
+    if session::logout(&request.refresh_token)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_refresh_token",
+            "Invalid or expired refresh token",
+        ))
+    }
+}
+
+/// `DELETE /v1/admin/sessions` ends every session, admin and member alike.
+/// the keys and personal tokens keep working and can log in again.
+pub async fn revoke_sessions_handler(caller: Caller) -> Result<Json<SessionsRevoked>, ApiError> {
+    caller.require_admin()?;
+
+    let revoked = session::revoke_everyone()?;
+    tracing::warn!(revoked, by = %caller.name(), "revoked every session");
+    Ok(Json(SessionsRevoked { revoked }))
+}
+
+/// `POST /v1/register`, closed unless `REGISTRATION` is `open` or
+/// `invite`. with a valid invite code the user is created at once (201);
+/// without one it waits in the approval queue (202).
+pub async fn register_handler(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<RegisterRequest>,
+) -> Result<Response, ApiError> {
+    match (Config::get().registration, &request.invite) {
+        (RegistrationMode::Closed, _) => {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "registration_closed",
+                "Registration is closed",
+            ))
+        }
+        (RegistrationMode::InviteOnly, None) => {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "invite_required",
+                "Registration needs an invite code",
+            ))
+        }
+        _ => {}
+    }
+
+    let user = User::create_user(
+        Some(request.username),
+        Some(request.languages),
+        request.discord_id,
+    )?;
+    let _guard = user::write_lock();
+    let Some(code) = request.invite else {
+        let queued = registration::enqueue(FILEPATH, &user)?;
+        registration::emit("requested", &user.username, "self");
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(RegisterResponse {
+                status: RegistrationStatus::Pending,
+                user: queued.user,
+            }),
+        )
+            .into_response());
+    };
+
+    if !registration::invite_is_valid(FILEPATH, &code)? {
+        crate::metrics::auth_failure("invite");
+        crate::limit::record_auth_failure(addr.ip());
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "invalid_invite",
+            "Invalid, spent or expired invite code",
+        ));
+    }
+    // the name is already claimed by a registration waiting for approval
+    if registration::is_pending(FILEPATH, &user.username)? {
+        return Err(DatabaseError::UserAlreadyExists.into());
+    }
+    user.save_to_csv(FILEPATH)?;
+    registration::redeem_invite(FILEPATH, &code)?;
+    registration::emit("invited", &user.username, "self");
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisterResponse {
+            status: RegistrationStatus::Approved,
+            user: user.into(),
+        }),
+    )
+        .into_response())
+}
+
+/// `GET /v1/admin/registrations`, oldest first.
+pub async fn list_registrations_handler(
+    caller: Caller,
+) -> Result<Json<Vec<Registration>>, ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::read_lock();
+    Ok(Json(registration::pending(FILEPATH)?))
+}
+
+/// `POST /v1/admin/registrations/{name}/approve` creates the user.
+pub async fn approve_registration_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<Json<protocol::User>, ApiError> {
+    caller.require_admin()?;
+
+    let user = {
+        let _guard = user::write_lock();
+        registration::approve(FILEPATH, &username)?
+    };
+    registration::emit("approved", &username, &caller.name());
+    Ok(Json(user.into()))
+}
+
+/// `POST /v1/admin/registrations/{name}/reject` drops the registration.
+pub async fn reject_registration_handler(
+    caller: Caller,
+    Path(username): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_admin()?;
+
+    {
+        let _guard = user::write_lock();
+        registration::reject(FILEPATH, &username)?;
+    }
+    registration::emit("rejected", &username, &caller.name());
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /v1/admin/invites`
+pub async fn create_invite_handler(
+    caller: Caller,
+    Json(request): Json<CreateInviteRequest>,
+) -> Result<(StatusCode, Json<Invite>), ApiError> {
+    caller.require_admin()?;
+
+    let uses = request.uses.unwrap_or(1);
+    if uses == 0 {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_uses",
+            "An invite needs at least one use",
+        ));
+    }
+    let expires_at = request
+        .expires_in
+        .map(|secs| {
+            registration::expiry(secs).ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "invalid_expiry",
+                    "expires_in is too far in the future",
+                )
+            })
+        })
+        .transpose()?;
+    let invite = {
+        let _guard = user::write_lock();
+        registration::create_invite(FILEPATH, uses, expires_at)?
+    };
+    tracing::info!(uses, expires_at = ?invite.expires_at, "created invite");
+    Ok((StatusCode::CREATED, Json(invite)))
+}
+
+/// `GET /v1/admin/invites`, the ones that can still be used.
+pub async fn list_invites_handler(caller: Caller) -> Result<Json<Vec<Invite>>, ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::read_lock();
+    Ok(Json(registration::invites(FILEPATH)?))
+}
+
+/// `DELETE /v1/admin/invites/{code}`
+pub async fn revoke_invite_handler(
+    caller: Caller,
+    Path(code): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_admin()?;
+
+    let _guard = user::write_lock();
+    if !registration::revoke_invite(FILEPATH, &code)? {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "invite_not_found",
+            "No such invite, or it is spent or expired",
+        ));
+    }
+    tracing::info!("revoked invite");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+    /// unix timestamp; when set only the version in effect at that time is returned
+    at: Option<u64>,
+}
+
+pub async fn history_handler(
+    _caller: Caller,
+    Path(username): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Response, ApiError> {
+    let _guard = user::read_lock();
+    match query.at {
+        Some(at) => {
+            let version: UserVersion = User::version_at(FILEPATH, &username, at)?;
+            Ok(Json(version).into_response())
+        }
+        None => {
+            let versions: Vec<UserVersion> = User::history(FILEPATH, &username)?;
+            Ok(Json(versions).into_response())
+        }
+    }
+}
+
+pub async fn revert_handler(
+    caller: Caller,
+    Path((username, version)): Path<(String, u32)>,
+) -> Result<Json<protocol::User>, ApiError> {
+    caller.require_admin()?;
+
+    let user = {
+        let _guard = user::write_lock();
+        User::revert_to(FILEPATH, &username, version)?
+    };
+    tracing::info!(user = %username, version, "reverted user");
+    Ok(Json(user.into()))
+}
+
+/// `POST /v1/users:import`. the router can't express a literal `:` inside a
+/// segment, so `/v1/:action` lands here and the action is matched by hand.
+pub async fn collection_action_handler(
+    caller: Caller,
+    headers: HeaderMap,
+    Path(action): Path<String>,
+    Query(options): Query<ImportOptions>,
+    body: String,
+) -> Result<Response, ApiError> {
+    if action != "users:import" {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "unknown_action",
+            format!("Unknown action {:?}", action),
+        ));
+    }
+    caller.require_admin()?;
+
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/csv"));
+    let rows = if is_csv {
+        import::parse_csv(&body)
+    } else {
+        import::parse_json(&body)
+    }
+    .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, "bad_roster", e.to_string()))?;
+
+    let report = {
+        let _guard = user::write_lock();
+        import::import_users(FILEPATH, rows, options)?
+    };
+    tracing::info!(
+        created = report.created,
+        overwritten = report.overwritten,
+        merged = report.merged,
+        skipped = report.skipped,
+        invalid = report.invalid,
+        failed = report.failed,
+        dry_run = report.dry_run,
+        applied = report.applied,
+        "imported users"
+    );
+
+    let status = if report.applied || report.dry_run {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status, Json(report)).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// `GET /v1/export?format=json|jsonl|csv|md`. the roster is read once so the
+/// export is a consistent snapshot, then encoded row by row into the body.
+pub async fn export_handler(
+    _caller: Caller,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let format = query.format;
+    let users = {
+        let _guard = user::read_lock();
+        User::all(FILEPATH)?
+    };
+    let count = users.len();
+    tracing::info!(count, "exporting users as {:?}", format);
+
+    let rows = stream::iter(users.into_iter().enumerate())
+        .map(move |(index, user)| export::row(format, index, &user));
+    let body = stream::once(async move { export::header(format) })
+        .chain(rows)
+        .chain(stream::once(async move { export::footer(format, count) }))
+        .map(Ok::<_, Infallible>);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let disposition = format!(
+        "attachment; filename=\"ccweb-users-{}.{}\"",
+        timestamp,
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// `POST /v1/admin/backup` snapshots the store into `BACKUP_DIR`.
+pub async fn backup_handler(
+    caller: Caller,
+) -> Result<(StatusCode, Json<BackupResponse>), ApiError> {
+    caller.require_admin()?;
+
+    let (archive, manifest) = {
+        let _guard = user::read_lock();
+        backup::create_backup(FILEPATH, &Config::get().backup_dir)?
+    };
+    tracing::info!(users = manifest.users, "created backup {}", archive);
+    Ok((
+        StatusCode::CREATED,
+        Json(BackupResponse { archive, manifest }),
+    ))
+}
+
+/// `GET /v1/admin/backups`
+pub async fn list_backups_handler(caller: Caller) -> Result<Json<Vec<BackupInfo>>, ApiError> {
+    caller.require_admin()?;
+
+    Ok(Json(backup::list_backups(&Config::get().backup_dir)?))
+}
+
+/// `POST /v1/admin/restore` validates an archive from `BACKUP_DIR` and swaps
+/// it in for the live store. the replaced store is kept as a new backup.
+pub async fn restore_handler(
+    caller: Caller,
+    Json(request): Json<RestoreRequest>,
+) -> Result<Json<RestoreResponse>, ApiError> {
+    caller.require_admin()?;
+
+    let dir = &Config::get().backup_dir;
+    let archive = backup::resolve_backup(dir, &request.archive)?;
+
+    if request.dry_run {
+        let manifest = {
+            let _guard = user::read_lock();
+            backup::verify_backup(FILEPATH, &archive)?
+        };
+        return Ok(Json(RestoreResponse {
+            restored: false,
+            manifest,
+            safety_backup: None,
+        }));
+    }
+
+    let (manifest, safety_backup) = {
+        let _guard = user::write_lock();
+        // the sessions are held in memory and would otherwise be written
+        // back over the restored file
+        session::restore_with(|| {
+            let restored = backup::restore_backup(FILEPATH, &archive, dir)?;
+            // an archive without one of the store files removes it
+            user::create_store(FILEPATH)?;
+            Ok::<_, ApiError>(restored)
+        })?
+    };
+    tracing::info!(
+        users = manifest.users,
+        "restored backup {}, previous store saved as {}",
+        request.archive,
+        safety_backup
+    );
+    Ok(Json(RestoreResponse {
+        restored: true,
+        manifest,
+        safety_backup: Some(safety_backup),
+    }))
+}
@@ -0,0 +1,489 @@
+use crate::registration::{invites_path, pending_path};
+use crate::session::sessions_path;
+use crate::token::tokens_path;
+use crate::user::{history_path, rewrite_bytes, User};
+use protocol::{BackupInfo, Manifest, ManifestFile};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// bumped whenever the archive layout changes.
+pub const ARCHIVE_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Debug)]
+pub enum BackupError {
+    NotFound(String),
+    Invalid(String),
+    IoError(io::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::NotFound(name) => write!(f, "Backup {} not found", name),
+            BackupError::Invalid(reason) => write!(f, "Invalid backup archive: {}", reason),
+            BackupError::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(error: io::Error) -> Self {
+        BackupError::IoError(error)
+    }
+}
+
+/// store files read out of an archive, as (name in the archive, contents).
+type ArchiveContents = Vec<(String, Vec<u8>)>;
+
+/// the files that make up the store, as (name in the archive, path on disk).
+fn store_files(file_path: &str) -> Vec<(String, PathBuf)> {
+    [
+        file_path.to_string(),
+        history_path(file_path),
+        tokens_path(file_path),
+        sessions_path(file_path),
+        pending_path(file_path),
+        invites_path(file_path),
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .map(|path| {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (name, path)
+    })
+    .collect()
+}
+
+/// writes every store file into a new timestamped archive in `dir`.
+/// the caller must hold the store read lock so the files agree with each other.
+pub fn create_backup(file_path: &str, dir: &Path) -> Result<(String, Manifest), BackupError> {
+    let mut contents = Vec::new();
+    for (name, path) in store_files(file_path) {
+        match fs::read(&path) {
+            Ok(data) => contents.push((name, data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let users = count_users(file_path, &contents)?;
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        created_at: now(),
+        users,
+        files: contents
+            .iter()
+            .map(|(name, data)| ManifestFile {
+                name: name.clone(),
+                size: data.len() as u64,
+                sha256: sha256(data),
+            })
+            .collect(),
+    };
+
+    fs::create_dir_all(dir)?;
+    let name = archive_name(dir, manifest.created_at);
+    let path = dir.join(&name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
+
+    let mut builder = tar::Builder::new(File::create(&tmp_path)?);
+    let manifest_json = serde_json::to_vec_pretty(&manifest).expect("manifest always serializes");
+    append(
+        &mut builder,
+        MANIFEST_NAME,
+        &manifest_json,
+        manifest.created_at,
+    )?;
+    for (name, data) in &contents {
+        append(&mut builder, name, data, manifest.created_at)?;
+    }
+    builder.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, &path)?;
+
+    Ok((name, manifest))
+}
+
+/// reads an archive and checks it against its manifest and the store format.
+/// nothing on disk is touched.
+pub fn verify_backup(file_path: &str, archive: &Path) -> Result<Manifest, BackupError> {
+    read_archive(file_path, archive).map(|(manifest, _)| manifest)
+}
+
+/// replaces the live store with the contents of `archive` after validating
+/// it. the current store is backed up to `dir` first; its name is returned.
+/// the caller must hold the store write lock.
+pub fn restore_backup(
+    file_path: &str,
+    archive: &Path,
+    dir: &Path,
+) -> Result<(Manifest, String), BackupError> {
+    let (manifest, contents) = read_archive(file_path, archive)?;
+    let (safety_backup, _) = create_backup(file_path, dir)?;
+
+    for (name, path) in store_files(file_path) {
+        match contents.iter().find(|(n, _)| *n == name) {
+            Some((_, data)) => rewrite_bytes(&path.to_string_lossy(), data)?,
+            None => match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+    }
+
+    Ok((manifest, safety_backup))
+}
+
+pub fn list_backups(dir: &Path) -> io::Result<Vec<BackupInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with("ccweb-backup-") && name.ends_with(".tar") {
+            backups.push(BackupInfo {
+                name,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    backups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(backups)
+}
+
+/// resolves an archive name from the api to a path inside `dir`, refusing
+/// anything that could point elsewhere.
+pub fn resolve_backup(dir: &Path, name: &str) -> Result<PathBuf, BackupError> {
+    let is_plain_name = !name.is_empty()
+        && !name.contains(['/', '\\'])
+        && name != "."
+        && name != ".."
+        && name.ends_with(".tar");
+    let path = dir.join(name);
+    if is_plain_name && path.is_file() {
+        Ok(path)
+    } else {
+        Err(BackupError::NotFound(name.to_string()))
+    }
+}
+
+fn read_archive(
+    file_path: &str,
+    archive: &Path,
+) -> Result<(Manifest, ArchiveContents), BackupError> {
+    let file = match File::open(archive) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(BackupError::NotFound(archive.display().to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut manifest = None;
+    let mut contents = Vec::new();
+    let mut archive = tar::Archive::new(file);
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let name = entry
+            .path()
+            .map_err(invalid)?
+            .to_string_lossy()
+            .into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(invalid)?;
+
+        if name == MANIFEST_NAME {
+            let parsed: Manifest = serde_json::from_slice(&data)
+                .map_err(|e| BackupError::Invalid(format!("bad manifest: {}", e)))?;
+            manifest = Some(parsed);
+        } else {
+            contents.push((name, data));
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| BackupError::Invalid("missing manifest".to_string()))?;
+    if manifest.version != ARCHIVE_VERSION {
+        return Err(BackupError::Invalid(format!(
+            "unsupported archive version {}",
+            manifest.version
+        )));
+    }
+
+    let known: Vec<String> = store_files(file_path).into_iter().map(|(n, _)| n).collect();
+    for (name, data) in &contents {
+        if !known.contains(name) {
+            return Err(BackupError::Invalid(format!("unexpected file {}", name)));
+        }
+        let Some(expected) = manifest.files.iter().find(|f| f.name == *name) else {
+            return Err(BackupError::Invalid(format!(
+                "{} is not in the manifest",
+                name
+            )));
+        };
+        if expected.size != data.len() as u64 || expected.sha256 != sha256(data) {
+            return Err(BackupError::Invalid(format!("{} is corrupt", name)));
+        }
+    }
+    for file in &manifest.files {
+        if !contents.iter().any(|(name, _)| *name == file.name) {
+            return Err(BackupError::Invalid(format!("{} is missing", file.name)));
+        }
+    }
+
+    let users = count_users(file_path, &contents)?;
+    if users != manifest.users {
+        return Err(BackupError::Invalid(format!(
+            "manifest lists {} users but the archive holds {}",
+            manifest.users, users
+        )));
+    }
+
+    Ok((manifest, contents))
+}
+
+/// parses the users file out of a set of store files, failing on any row the
+/// store couldn't read back. returns the number of live users.
+fn count_users(file_path: &str, contents: &[(String, Vec<u8>)]) -> Result<usize, BackupError> {
+    let users_name = &store_files(file_path)[0].0;
+    let Some((_, data)) = contents.iter().find(|(name, _)| name == users_name) else {
+        return Ok(0);
+    };
+    let text = std::str::from_utf8(data)
+        .map_err(|_| BackupError::Invalid(format!("{} is not utf-8", users_name)))?;
+
+    let mut seen = HashSet::new();
+    let mut live = 0;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let user = User::from_csv_line(line).ok_or_else(|| {
+            BackupError::Invalid(format!("{} line {} is malformed", users_name, i + 1))
+        })?;
+        if !seen.insert(user.username.clone()) {
+            return Err(BackupError::Invalid(format!(
+                "{} lists {} twice",
+                users_name, user.username
+            )));
+        }
+        if user.deleted_at.is_none() {
+            live += 1;
+        }
+    }
+    Ok(live)
+}
+
+fn append(builder: &mut tar::Builder<File>, name: &str, data: &[u8], mtime: u64) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+fn archive_name(dir: &Path, created_at: u64) -> String {
+    let mut name = format!("ccweb-backup-{}.tar", created_at);
+    let mut n = 1;
+    while dir.join(&name).exists() {
+        name = format!("ccweb-backup-{}-{}.tar", created_at, n);
+        n += 1;
+    }
+    name
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn invalid(error: io::Error) -> BackupError {
+    BackupError::Invalid(error.to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const USERS: &str = "fork,Rust|C,1\nalice,Haskell,2\nbob,Go,3,1700000000\n";
+
+    /// a store with two live users and a soft deleted one.
+    fn store(dir: &TempDir) -> String {
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, USERS).unwrap();
+        fs::write(history_path(&file_path), "").unwrap();
+        file_path
+    }
+
+    /// an archive holding `files` next to `manifest`, which need not agree.
+    fn archive(dir: &TempDir, manifest: &Manifest, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.path().join("handmade.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        let json = serde_json::to_vec(manifest).unwrap();
+        append(&mut builder, MANIFEST_NAME, &json, 0).unwrap();
+        for (name, data) in files {
+            append(&mut builder, name, data, 0).unwrap();
+        }
+        builder.into_inner().unwrap();
+        path
+    }
+
+    fn manifest(users: usize, files: &[(&str, &[u8])]) -> Manifest {
+        Manifest {
+            version: ARCHIVE_VERSION,
+            created_at: 0,
+            users,
+            files: files
+                .iter()
+                .map(|(name, data)| ManifestFile {
+                    name: name.to_string(),
+                    size: data.len() as u64,
+                    sha256: sha256(data),
+                })
+                .collect(),
+        }
+    }
+
+    fn invalid_reason(result: Result<Manifest, BackupError>) -> String {
+        match result {
+            Err(BackupError::Invalid(reason)) => reason,
+            other => panic!("expected an invalid archive, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn backup_verifies_and_counts_live_users() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let (name, manifest) = create_backup(&file_path, &dir.path().join("backups")).unwrap();
+        assert_eq!(manifest.users, 2);
+        let verified = verify_backup(&file_path, &dir.path().join("backups").join(name)).unwrap();
+        assert_eq!(verified.users, 2);
+        assert_eq!(verified.files.len(), manifest.files.len());
+    }
+
+    #[test]
+    fn backup_holds_the_sidecars() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        fs::write(crate::token::tokens_path(&file_path), "fork,abc,1\n").unwrap();
+
+        let (_, manifest) = create_backup(&file_path, dir.path()).unwrap();
+        let names: Vec<&str> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+        assert!(names.contains(&"users.csv"));
+        assert!(names.contains(&"users.tokens.csv"));
+    }
+
+    #[test]
+    fn corrupt_file_is_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let listed = manifest(2, &[("users.csv", USERS.as_bytes())]);
+        let tampered = USERS.replace("fork", "fake");
+        let path = archive(&dir, &listed, &[("users.csv", tampered.as_bytes())]);
+
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("corrupt"));
+    }
+
+    #[test]
+    fn unexpected_file_is_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let files: &[(&str, &[u8])] = &[("users.csv", USERS.as_bytes()), ("passwd", b"x")];
+        let path = archive(&dir, &manifest(2, files), files);
+
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("unexpected"));
+    }
+
+    #[test]
+    fn missing_file_is_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let listed = manifest(
+            2,
+            &[("users.csv", USERS.as_bytes()), ("users.history.csv", b"")],
+        );
+        let path = archive(&dir, &listed, &[("users.csv", USERS.as_bytes())]);
+
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("missing"));
+    }
+
+    #[test]
+    fn wrong_version_is_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let files: &[(&str, &[u8])] = &[("users.csv", USERS.as_bytes())];
+        let mut listed = manifest(2, files);
+        listed.version = ARCHIVE_VERSION + 1;
+        let path = archive(&dir, &listed, files);
+
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("version"));
+    }
+
+    #[test]
+    fn wrong_user_count_is_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let files: &[(&str, &[u8])] = &[("users.csv", USERS.as_bytes())];
+        let path = archive(&dir, &manifest(3, files), files);
+
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("users"));
+    }
+
+    #[test]
+    fn malformed_or_duplicate_rows_are_refused() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let malformed = b"fork\n";
+        let files: &[(&str, &[u8])] = &[("users.csv", malformed)];
+        let path = archive(&dir, &manifest(0, files), files);
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("malformed"));
+
+        let duplicate = b"fork,Rust,1\nfork,C,2\n";
+        let files: &[(&str, &[u8])] = &[("users.csv", duplicate)];
+        let path = archive(&dir, &manifest(2, files), files);
+        assert!(invalid_reason(verify_backup(&file_path, &path)).contains("twice"));
+    }
+
+    #[test]
+    fn restore_replaces_the_store_and_keeps_the_old_one() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let backups = dir.path().join("backups");
+        let (name, _) = create_backup(&file_path, &backups).unwrap();
+
+        fs::write(&file_path, "eve,C,9\n").unwrap();
+        let (manifest, safety) = restore_backup(&file_path, &backups.join(name), &backups).unwrap();
+
+        assert_eq!(manifest.users, 2);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), USERS);
+        let previous = verify_backup(&file_path, &backups.join(safety)).unwrap();
+        assert_eq!(previous.users, 1);
+    }
+}
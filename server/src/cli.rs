@@ -0,0 +1,199 @@
+use crate::backup;
+use crate::config::Config;
+use crate::import;
+use crate::server::FILEPATH;
+use crate::user;
+use protocol::{ConflictPolicy, ImportMode, ImportOptions};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "usage:
+    CCweb                 run the server
+    CCweb import <file.csv|file.json> [--dry-run] [--best-effort] [--on-conflict skip|overwrite|merge]
+    CCweb backup [--dir <dir>]
+    CCweb restore <archive.tar> [--dir <dir>] [--yes]
+
+import, backup and restore work on the files directly and refuse to run
+while the server is; use POST /v1/users:import, /v1/admin/backup and
+/v1/admin/restore instead.";
+
+/// admin commands that work on the local store without starting the server.
+/// returns `None` when no command was given.
+pub fn run(args: &[String]) -> Option<ExitCode> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "import" => claim().and_then(|_claim| import_command(rest)),
+        "backup" => claim().and_then(|_claim| backup_command(rest)),
+        "restore" => claim().and_then(|_claim| restore_command(rest)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {:?}\n{}", command, USAGE)),
+    };
+
+    Some(match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    })
+}
+
+fn import_command(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut options = ImportOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--best-effort" => options.mode = ImportMode::BestEffort,
+            "--on-conflict" => {
+                options.on_conflict = match args.next().map(String::as_str) {
+                    Some("skip") => ConflictPolicy::Skip,
+                    Some("overwrite") => ConflictPolicy::Overwrite,
+                    Some("merge") => ConflictPolicy::Merge,
+                    other => return Err(format!("invalid --on-conflict value {:?}", other)),
+                }
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}\n{}", arg, USAGE)),
+        }
+    }
+    let path = path.ok_or_else(|| USAGE.to_string())?;
+
+    let body = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let rows = if path.ends_with(".json") {
+        import::parse_json(&body)
+    } else {
+        import::parse_csv(&body)
+    }
+    .map_err(|e| e.to_string())?;
+
+    let report = {
+        let _guard = user::write_lock();
+        import::import_users(FILEPATH, rows, options).map_err(|e| e.to_string())?
+    };
+    for row in &report.rows {
+        match &row.message {
+            Some(message) => println!(
+                "{:>4} {:<20} {:?}: {}",
+                row.row, row.username, row.status, message
+            ),
+            None => println!("{:>4} {:<20} {:?}", row.row, row.username, row.status),
+        }
+    }
+    println!(
+        "{} created, {} overwritten, {} merged, {} skipped, {} invalid, {} failed",
+        report.created,
+        report.overwritten,
+        report.merged,
+        report.skipped,
+        report.invalid,
+        report.failed
+    );
+
+    if report.dry_run {
+        println!("dry run, nothing was written");
+        Ok(())
+    } else if report.applied {
+        Ok(())
+    } else {
+        Err("import rejected, nothing was written".to_string())
+    }
+}
+
+fn backup_command(args: &[String]) -> Result<(), String> {
+    let mut dir = Config::get().backup_dir.clone();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => dir = dir_arg(args.next())?,
+            _ => return Err(format!("unexpected argument {:?}\n{}", arg, USAGE)),
+        }
+    }
+
+    let (name, manifest) = {
+        let _guard = user::read_lock();
+        backup::create_backup(FILEPATH, &dir).map_err(|e| e.to_string())?
+    };
+    println!(
+        "wrote {} ({} users, {} files)",
+        dir.join(name).display(),
+        manifest.users,
+        manifest.files.len()
+    );
+    Ok(())
+}
+
+fn restore_command(args: &[String]) -> Result<(), String> {
+    let mut archive = None;
+    let mut dir = Config::get().backup_dir.clone();
+    let mut yes = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => dir = dir_arg(args.next())?,
+            "--yes" | "-y" => yes = true,
+            _ if archive.is_none() => archive = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}\n{}", arg, USAGE)),
+        }
+    }
+    let archive = Path::new(archive.ok_or_else(|| USAGE.to_string())?);
+
+    let _guard = user::write_lock();
+    let manifest = backup::verify_backup(FILEPATH, archive).map_err(|e| e.to_string())?;
+    println!(
+        "{} is valid: {} users, {} files, taken at {}",
+        archive.display(),
+        manifest.users,
+        manifest.files.len(),
+        manifest.created_at
+    );
+
+    if !yes && !confirm("replace the live store with this backup?")? {
+        return Err("restore cancelled".to_string());
+    }
+
+    let (_, safety_backup) =
+        backup::restore_backup(FILEPATH, archive, &dir).map_err(|e| e.to_string())?;
+    println!(
+        "restored; the previous store was saved as {}",
+        dir.join(safety_backup).display()
+    );
+    Ok(())
+}
+
+/// keeps the server, or another command, off the store while this one works
+/// on it.
+fn claim() -> Result<fs::File, String> {
+    match user::claim_store(FILEPATH) {
+        Ok(Some(claim)) => Ok(claim),
+        Ok(None) => Err("the store is in use, is the server running?".to_string()),
+        Err(e) => Err(format!("failed to lock the store: {}", e)),
+    }
+}
+
+fn dir_arg(value: Option<&String>) -> Result<PathBuf, String> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| "--dir needs a value".to_string())
+}
+
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "yes"))
+}
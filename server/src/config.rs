@@ -0,0 +1,307 @@
+use std::{env, path::PathBuf, sync::OnceLock, time::Duration};
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+const DEFAULT_PURGE_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_TLS_RELOAD_INTERVAL_SECS: u64 = 60;
+const DEFAULT_READ_RATE_LIMIT: u64 = 300;
+const DEFAULT_READ_BURST: u64 = 60;
+const DEFAULT_WRITE_RATE_LIMIT: u64 = 60;
+const DEFAULT_WRITE_BURST: u64 = 20;
+const DEFAULT_AUTH_FAILURE_LIMIT: u64 = 10;
+const DEFAULT_AUTH_FAILURE_WINDOW_SECS: u64 = 5 * 60;
+const DEFAULT_LOCKOUT_SECS: u64 = 15 * 60;
+const DEFAULT_MAX_BODY_BYTES: u64 = 2 * 1024 * 1024;
+const DEFAULT_MAX_HEADER_BYTES: u64 = 16 * 1024;
+const DEFAULT_MAX_SEGMENT_LEN: u64 = 256;
+const DEFAULT_MAX_USERNAME_LEN: u64 = 64;
+const DEFAULT_MAX_DISCORD_ID_LEN: u64 = 64;
+const DEFAULT_MAX_LANGUAGES: u64 = 32;
+const DEFAULT_MAX_USERS: u64 = 100_000;
+const DEFAULT_MAX_PENDING: u64 = 1_000;
+const DEFAULT_ACCESS_TOKEN_TTL_SECS: u64 = 15 * 60;
+const DEFAULT_REFRESH_TOKEN_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+/// shorter secrets make the hmac on access tokens guessable.
+const MIN_SESSION_SECRET_LEN: usize = 32;
+
+/// server settings read from the environment (and `.env`) once at startup.
+#[derive(Debug)]
+pub struct Config {
+    /// how long soft deleted users can still be restored before they are purged.
+    pub retention: Duration,
+    /// how often the purge job runs.
+    pub purge_interval: Duration,
+    /// where backup archives are written and restored from.
+    pub backup_dir: PathBuf,
+    /// how long a shutdown waits for in-flight requests before giving up on
+    /// them. store writes are always waited for.
+    pub shutdown_timeout: Duration,
+    /// serve https with these files instead of plain http.
+    pub tls: Option<TlsPaths>,
+    /// how often the certificate files are checked for changes.
+    pub tls_reload_interval: Duration,
+    /// limits for reads, applied to each client ip and each api key.
+    pub read_limit: RateLimit,
+    /// limits for anything that writes, applied the same way.
+    pub write_limit: RateLimit,
+    /// failed authentications from one ip within `auth_failure_window` that
+    /// get it locked out for `lockout`. 0 turns lockout off.
+    pub auth_failure_limit: u64,
+    pub auth_failure_window: Duration,
+    pub lockout: Duration,
+    pub limits: Limits,
+    /// who may use `POST /v1/register`.
+    pub registration: RegistrationMode,
+    /// a `tracing` filter such as `info` or `CCweb=debug,tower_http=warn`.
+    pub log_level: String,
+    pub log_format: LogFormat,
+    /// signs access tokens. when unset a random one is made at startup, so
+    /// access tokens stop working when the server restarts. sessions are
+    /// kept on disk, so clients get a new one with their refresh token.
+    pub session_secret: Option<String>,
+    /// how long an access token from `/v1/auth/login` is accepted.
+    pub access_token_ttl: Duration,
+    /// how long a session can be refreshed without logging in again.
+    pub refresh_token_ttl: Duration,
+    /// the shared key every request must present. empty when `API_KEY` is
+    /// unset, which `problems` reports.
+    pub api_key: String,
+    /// everything wrong with the environment, like a missing `API_KEY` or a
+    /// number that doesn't parse. the server refuses to start unless this is
+    /// empty; the cli commands, which don't serve requests, carry on.
+    pub problems: Vec<String>,
+}
+
+/// a token bucket: `burst` requests at once, refilled at `per_minute`.
+/// a `per_minute` of 0 turns the limit off.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub per_minute: u64,
+    pub burst: u64,
+}
+
+/// bounds on what a request may send and the store may hold.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_body_bytes: usize,
+    /// all header names and values together.
+    pub max_header_bytes: usize,
+    /// any one `/`-separated piece of the path, before percent decoding.
+    pub max_segment_len: usize,
+    pub max_username_len: usize,
+    pub max_discord_id_len: usize,
+    pub max_languages: usize,
+    /// rows in the store, soft deleted users included.
+    pub max_users: usize,
+    /// registrations waiting for approval.
+    pub max_pending: usize,
+}
+
+/// who may register without the admin key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// anyone; without an invite code the registration waits for approval.
+    Open,
+    /// only holders of an invite code.
+    InviteOnly,
+    /// nobody; the default until `REGISTRATION` opens it up.
+    Closed,
+}
+
+/// pem files from `TLS_CERT` and `TLS_KEY`.
+#[derive(Debug, Clone)]
+pub struct TlsPaths {
+    /// the certificate chain, leaf first.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// how log lines are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// one human readable line per event.
+    Text,
+    /// one json object per event, for log collectors.
+    Json,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    pub fn get() -> &'static Config {
+        CONFIG.get_or_init(Config::from_env)
+    }
+
+    fn from_env() -> Self {
+        dotenv::dotenv().ok();
+        let mut problems = Vec::new();
+
+        let retention_days = env_u64(
+            "DELETED_RETENTION_DAYS",
+            DEFAULT_RETENTION_DAYS,
+            &mut problems,
+        );
+        let purge_interval = env_u64(
+            "PURGE_INTERVAL_SECS",
+            DEFAULT_PURGE_INTERVAL_SECS,
+            &mut problems,
+        );
+        let shutdown_timeout = env_u64(
+            "SHUTDOWN_TIMEOUT_SECS",
+            DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            &mut problems,
+        );
+
+        // RUST_LOG is what most tracing users reach for first
+        let log_level = env::var("LOG_LEVEL")
+            .or_else(|_| env::var("RUST_LOG"))
+            .unwrap_or_else(|_| "info".to_string());
+        if let Err(e) = EnvFilter::try_new(&log_level) {
+            problems.push(format!("invalid LOG_LEVEL={:?}: {}", log_level, e));
+        }
+
+        let log_format = match env::var("LOG_FORMAT").as_deref().map(str::trim) {
+            Ok("json") => LogFormat::Json,
+            Ok("text") | Err(_) => LogFormat::Text,
+            Ok(other) => {
+                problems.push(format!(
+                    "invalid LOG_FORMAT={:?}, expected text or json",
+                    other
+                ));
+                LogFormat::Text
+            }
+        };
+
+        let tls = match (env::var_os("TLS_CERT"), env::var_os("TLS_KEY")) {
+            (Some(cert), Some(key)) => Some(TlsPaths {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            (None, None) => None,
+            _ => {
+                problems.push("TLS_CERT and TLS_KEY must be set together".to_string());
+                None
+            }
+        };
+        let tls_reload_interval = env_u64(
+            "TLS_RELOAD_INTERVAL_SECS",
+            DEFAULT_TLS_RELOAD_INTERVAL_SECS,
+            &mut problems,
+        );
+
+        let read_limit = RateLimit {
+            per_minute: env_u64("READ_RATE_LIMIT", DEFAULT_READ_RATE_LIMIT, &mut problems),
+            burst: env_u64("READ_BURST", DEFAULT_READ_BURST, &mut problems).max(1),
+        };
+        let write_limit = RateLimit {
+            per_minute: env_u64("WRITE_RATE_LIMIT", DEFAULT_WRITE_RATE_LIMIT, &mut problems),
+            burst: env_u64("WRITE_BURST", DEFAULT_WRITE_BURST, &mut problems).max(1),
+        };
+        let auth_failure_limit = env_u64(
+            "AUTH_FAILURE_LIMIT",
+            DEFAULT_AUTH_FAILURE_LIMIT,
+            &mut problems,
+        );
+        let auth_failure_window = env_u64(
+            "AUTH_FAILURE_WINDOW_SECS",
+            DEFAULT_AUTH_FAILURE_WINDOW_SECS,
+            &mut problems,
+        );
+        let lockout = env_u64("LOCKOUT_SECS", DEFAULT_LOCKOUT_SECS, &mut problems);
+
+        let mut limit = |name, default| env_u64(name, default, &mut problems) as usize;
+        let limits = Limits {
+            max_body_bytes: limit("MAX_BODY_BYTES", DEFAULT_MAX_BODY_BYTES),
+            max_header_bytes: limit("MAX_HEADER_BYTES", DEFAULT_MAX_HEADER_BYTES),
+            max_segment_len: limit("MAX_SEGMENT_LEN", DEFAULT_MAX_SEGMENT_LEN),
+            max_username_len: limit("MAX_USERNAME_LEN", DEFAULT_MAX_USERNAME_LEN),
+            max_discord_id_len: limit("MAX_DISCORD_ID_LEN", DEFAULT_MAX_DISCORD_ID_LEN),
+            max_languages: limit("MAX_LANGUAGES", DEFAULT_MAX_LANGUAGES),
+            max_users: limit("MAX_USERS", DEFAULT_MAX_USERS),
+            max_pending: limit("MAX_PENDING_REGISTRATIONS", DEFAULT_MAX_PENDING),
+        };
+
+        let registration = match env::var("REGISTRATION").as_deref().map(str::trim) {
+            Ok("open") => RegistrationMode::Open,
+            Ok("invite") => RegistrationMode::InviteOnly,
+            Ok("closed") | Err(_) => RegistrationMode::Closed,
+            Ok(other) => {
+                problems.push(format!(
+                    "invalid REGISTRATION={:?}, expected open, invite or closed",
+                    other
+                ));
+                RegistrationMode::Closed
+            }
+        };
+
+        let session_secret = env::var("SESSION_SECRET").ok();
+        if session_secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() < MIN_SESSION_SECRET_LEN)
+        {
+            problems.push(format!(
+                "SESSION_SECRET must be at least {} bytes",
+                MIN_SESSION_SECRET_LEN
+            ));
+        }
+        let access_token_ttl = env_u64(
+            "ACCESS_TOKEN_TTL_SECS",
+            DEFAULT_ACCESS_TOKEN_TTL_SECS,
+            &mut problems,
+        );
+        let refresh_token_ttl = env_u64(
+            "REFRESH_TOKEN_TTL_SECS",
+            DEFAULT_REFRESH_TOKEN_TTL_SECS,
+            &mut problems,
+        );
+        if refresh_token_ttl < access_token_ttl {
+            problems.push(
+                "REFRESH_TOKEN_TTL_SECS must not be shorter than ACCESS_TOKEN_TTL_SECS".to_string(),
+            );
+        }
+
+        let api_key = env::var("API_KEY").unwrap_or_default();
+        if api_key.trim().is_empty() {
+            problems.push("API_KEY is not set; put it in the environment or .env".to_string());
+        }
+
+        Self {
+            retention: Duration::from_secs(retention_days.saturating_mul(24 * 60 * 60)),
+            purge_interval: Duration::from_secs(purge_interval.max(1)),
+            backup_dir: env::var("BACKUP_DIR")
+                .unwrap_or_else(|_| "./backups".to_string())
+                .into(),
+            shutdown_timeout: Duration::from_secs(shutdown_timeout),
+            tls,
+            tls_reload_interval: Duration::from_secs(tls_reload_interval.max(1)),
+            read_limit,
+            write_limit,
+            auth_failure_limit,
+            auth_failure_window: Duration::from_secs(auth_failure_window),
+            lockout: Duration::from_secs(lockout),
+            limits,
+            registration,
+            log_level,
+            log_format,
+            session_secret,
+            access_token_ttl: Duration::from_secs(access_token_ttl),
+            refresh_token_ttl: Duration::from_secs(refresh_token_ttl),
+            api_key,
+            problems,
+        }
+    }
+}
+
+fn env_u64(name: &str, default: u64, problems: &mut Vec<String>) -> u64 {
+    match env::var(name) {
+        Ok(value) => match value.trim().parse() {
+            Ok(value) => value,
+            Err(_) => {
+                problems.push(format!("invalid {}={:?}, expected a number", name, value));
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
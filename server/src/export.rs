@@ -0,0 +1,123 @@
+use crate::user::User;
+use protocol::ExportFormat;
+
+/// text written before the first user.
+pub fn header(format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Json => "[".to_string(),
+        ExportFormat::Jsonl => String::new(),
+        ExportFormat::Csv => "username,languages,discord_id\r\n".to_string(),
+        ExportFormat::Md => {
+            "| Username | Languages | Discord ID |\n| --- | --- | --- |\n".to_string()
+        }
+    }
+}
+
+/// one user; `index` is its position in the export.
+pub fn row(format: ExportFormat, index: usize, user: &User) -> String {
+    match format {
+        ExportFormat::Json => {
+            let separator = if index == 0 { "\n" } else { ",\n" };
+            format!("{}{}", separator, to_json(user))
+        }
+        ExportFormat::Jsonl => format!("{}\n", to_json(user)),
+        ExportFormat::Csv => format!(
+            "{},{},{}\r\n",
+            csv_field(&user.username),
+            csv_field(&languages(user, "|")),
+            csv_field(&user.discord_id)
+        ),
+        ExportFormat::Md => format!(
+            "| {} | {} | {} |\n",
+            md_cell(&user.username),
+            md_cell(&languages(user, ", ")),
+            md_cell(&user.discord_id)
+        ),
+    }
+}
+
+/// text written after the last user.
+pub fn footer(format: ExportFormat, count: usize) -> String {
+    match format {
+        ExportFormat::Json if count == 0 => "]\n".to_string(),
+        ExportFormat::Json => "\n]\n".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn to_json(user: &User) -> String {
+    serde_json::to_string(&protocol::User::from(user.clone())).expect("user always serializes")
+}
+
+fn languages(user: &User, separator: &str) -> String {
+    user.languages
+        .iter()
+        .map(|l| format!("{:?}", l))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// quotes a csv field when needed and defuses values a spreadsheet would
+/// read as a formula by prefixing them with `'`.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn md_cell(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_defuses_formulas() {
+        for value in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert!(
+                csv_field(value).trim_start_matches('"').starts_with('\''),
+                "{:?}",
+                value
+            );
+        }
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+    }
+
+    #[test]
+    fn csv_leaves_plain_values_alone() {
+        assert_eq!(csv_field("fork"), "fork");
+        assert_eq!(csv_field("a=b"), "a=b");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn csv_quotes_separators() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+    }
+
+    #[test]
+    fn csv_row_defuses_every_field() {
+        let user = User {
+            username: "=cmd".to_string(),
+            languages: Vec::new(),
+            discord_id: "+123".to_string(),
+            deleted_at: None,
+        };
+        assert_eq!(row(ExportFormat::Csv, 0, &user), "'=cmd,,'+123\r\n");
+    }
+}
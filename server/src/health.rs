@@ -0,0 +1,144 @@
+use crate::config::Config;
+use crate::server::FILEPATH;
+use crate::token::tokens_path;
+use crate::user::{self, history_path, User};
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+};
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// by name: `store`, `key_store` and `config`.
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn from_result(result: Result<String, String>) -> Self {
+        match result {
+            Ok(detail) => Check { ok: true, detail },
+            Err(detail) => Check { ok: false, detail },
+        }
+    }
+}
+
+/// `GET /healthz`: the process is up and answering. says nothing about
+/// whether requests would succeed; that's `/readyz`.
+pub async fn healthz_handler() -> Json<Health> {
+    Json(Health { status: "ok" })
+}
+
+/// `GET /readyz`: 200 when every check passes, 503 with the failing ones
+/// otherwise.
+pub async fn readyz_handler() -> impl IntoResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert("store", Check::from_result(check_store()));
+    checks.insert("key_store", Check::from_result(check_key_store()));
+    checks.insert("config", Check::from_result(check_config()));
+
+    let ready = checks.values().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Readiness { ready, checks }))
+}
+
+/// the store and its history exist, parse and aren't read-only, and their
+/// directory takes new files. both are created at startup, so a missing one
+/// was removed under the server. they are only opened for reading: a check
+/// must not change what it checks.
+fn check_store() -> Result<String, String> {
+    let _guard = user::read_lock();
+    for path in [FILEPATH.to_string(), history_path(FILEPATH)] {
+        check_file(&path)?;
+    }
+    check_dir(FILEPATH)?;
+    User::all(FILEPATH).map_err(|e| format!("{} is not readable: {}", FILEPATH, e))?;
+    Ok("readable and writable".to_string())
+}
+
+/// the admin key is loaded and the personal tokens can be read.
+fn check_key_store() -> Result<String, String> {
+    if Config::get().api_key.trim().is_empty() {
+        return Err("no api key loaded".to_string());
+    }
+    let path = tokens_path(FILEPATH);
+    let _guard = user::read_lock();
+    check_file(&path)?;
+    fs::read_to_string(&path).map_err(|e| format!("{} is not readable: {}", path, e))?;
+    Ok("api key loaded, token store readable".to_string())
+}
+
+fn check_file(path: &str) -> Result<(), String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!("{} is missing", path))
+        }
+        Err(e) => return Err(format!("{} is not readable: {}", path, e)),
+    };
+    let metadata = file
+        .metadata()
+        .map_err(|e| format!("{} is not readable: {}", path, e))?;
+    if metadata.permissions().readonly() {
+        return Err(format!("{} is read-only", path));
+    }
+    Ok(())
+}
+
+/// a write replaces the store by renaming a new file over it, which needs
+/// the directory to be writable and not just the store. probed with a file
+/// of its own that is removed right away.
+fn check_dir(file_path: &str) -> Result<(), String> {
+    let probe = format!("{}.{}.probe", file_path, uuid::Uuid::new_v4().simple());
+    let not_writable =
+        |e: io::Error| format!("the directory of {} is not writable: {}", file_path, e);
+    File::create_new(&probe).map_err(not_writable)?;
+    fs::remove_file(&probe).map_err(not_writable)
+}
+
+fn check_config() -> Result<String, String> {
+    let problems = &Config::get().problems;
+    if problems.is_empty() {
+        Ok("valid".to_string())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn directory_probe_leaves_nothing_behind() {
+        let dir = TempDir::new();
+        assert_eq!(check_dir(&dir.file("users.csv")), Ok(()));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn missing_directory_is_not_writable() {
+        let dir = TempDir::new();
+        let file_path = dir.path().join("gone").join("users.csv");
+        assert!(check_dir(&file_path.to_string_lossy()).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::server::parse_languages;
+use crate::user::{history_path, rewrite_bytes, DatabaseError, User};
+use protocol::{
+    ConflictPolicy, ImportLanguages, ImportMode, ImportOptions, ImportReport, ImportRow, Language,
+    RowResult, RowStatus,
+};
+use std::{collections::HashSet, fmt, fs, io, str::FromStr};
+
+#[derive(Debug)]
+pub enum ImportError {
+    BadJson(serde_json::Error),
+    BadCsv { line: usize, message: String },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::BadJson(e) => write!(f, "Invalid JSON roster: {}", e),
+            ImportError::BadCsv { line, message } => {
+                write!(f, "Invalid CSV roster on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+pub fn parse_json(body: &str) -> Result<Vec<ImportRow>, ImportError> {
+    serde_json::from_str(body).map_err(ImportError::BadJson)
+}
+
+/// `username,languages,discord_id` with languages separated by `|`, the same
+/// layout as `users.csv`. a header row is optional.
+pub fn parse_csv(body: &str) -> Result<Vec<ImportRow>, ImportError> {
+    let mut rows = Vec::new();
+    for (i, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+        if i == 0 && parts[0].eq_ignore_ascii_case("username") {
+            continue;
+        }
+        if parts.len() > 3 {
+            return Err(ImportError::BadCsv {
+                line: i + 1,
+                message: format!("expected at most 3 columns, found {}", parts.len()),
+            });
+        }
+        rows.push(ImportRow {
+            username: parts[0].to_string(),
+            languages: ImportLanguages::Joined(parts.get(1).unwrap_or(&"").to_string()),
+            discord_id: parts.get(2).map(|s| s.to_string()),
+        });
+    }
+    Ok(rows)
+}
+
+/// validates every row, then applies them according to `options`.
+pub fn import_users(
+    file_path: &str,
+    rows: Vec<ImportRow>,
+    options: ImportOptions,
+) -> Result<ImportReport, DatabaseError> {
+    let mut results = Vec::with_capacity(rows.len());
+    let mut planned: Vec<(usize, User)> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, row) in rows.into_iter().enumerate() {
+        let username = row.username.clone();
+        match validate_row(row) {
+            Ok(user) if !seen.insert(user.username.clone()) => results.push(RowResult {
+                row: i + 1,
+                username,
+                status: RowStatus::Invalid,
+                message: Some("duplicate username in import".to_string()),
+            }),
+            Ok(user) => {
+                planned.push((results.len(), user));
+                results.push(RowResult {
+                    row: i + 1,
+                    username,
+                    status: RowStatus::Created,
+                    message: None,
+                });
+            }
+            Err(message) => results.push(RowResult {
+                row: i + 1,
+                username,
+                status: RowStatus::Invalid,
+                message: Some(message),
+            }),
+        }
+    }
+
+    // work out what each valid row would do against the current store
+    for (index, user) in &planned {
+        let (status, message) = plan_row(file_path, user, options.on_conflict)?;
+        results[*index].status = status;
+        results[*index].message = message;
+    }
+
+    let has_errors = results
+        .iter()
+        .any(|r| r.status == RowStatus::Invalid || r.status == RowStatus::Failed);
+    let reject = options.mode == ImportMode::Atomic && has_errors;
+
+    let mut applied = false;
+    if !options.dry_run && !reject {
+        let snapshot = match options.mode {
+            ImportMode::Atomic => Some(Snapshot::take(file_path)?),
+            ImportMode::BestEffort => None,
+        };
+
+        let mut failed = false;
+        for (index, user) in planned {
+            if results[index].status == RowStatus::Failed {
+                continue;
+            }
+            if let Err(e) = apply_row(file_path, user, results[index].status) {
+                results[index].status = RowStatus::Failed;
+                results[index].message = Some(e.to_string());
+                failed = true;
+                if snapshot.is_some() {
+                    break;
+                }
+            }
+        }
+
+        match snapshot {
+            Some(snapshot) if failed => snapshot.restore()?,
+            _ => applied = true,
+        }
+    }
+
+    Ok(report(options, applied, results))
+}
+
+fn validate_row(row: ImportRow) -> Result<User, String> {
+    let username = row.username.trim().to_string();
+    if username.is_empty() {
+        return Err("username is empty".to_string());
+    }
+
+    let languages = match row.languages {
+        ImportLanguages::Joined(joined) => parse_languages(&joined)
+            .map_err(|language| format!("unknown language {:?}", language))?,
+        ImportLanguages::List(list) => {
+            let mut languages = Vec::new();
+            for language in list {
+                match Language::from_str(&language) {
+                    Ok(language) if !languages.contains(&language) => languages.push(language),
+                    Ok(_) => {}
+                    Err(_) => return Err(format!("unknown language {:?}", language)),
+                }
+            }
+            languages
+        }
+    };
+
+    // create_user checks the lengths and reserved characters
+    let discord_id = row.discord_id.map(|id| id.trim().to_string());
+    User::create_user(Some(username), Some(languages), discord_id).map_err(|e| e.to_string())
+}
+
+fn plan_row(
+    file_path: &str,
+    user: &User,
+    on_conflict: ConflictPolicy,
+) -> Result<(RowStatus, Option<String>), DatabaseError> {
+    match User::lookup_user(file_path, &user.username) {
+        Ok(_) => Ok(match on_conflict {
+            ConflictPolicy::Skip => (RowStatus::Skipped, Some("user already exists".to_string())),
+            ConflictPolicy::Overwrite => (RowStatus::Overwritten, None),
+            ConflictPolicy::Merge => (RowStatus::Merged, None),
+        }),
+        Err(DatabaseError::UserNotFound) if User::exists(file_path, &user.username)? => Ok((
+            RowStatus::Failed,
+            Some("username belongs to a deleted user".to_string()),
+        )),
+        Err(DatabaseError::UserNotFound) => Ok((RowStatus::Created, None)),
+        Err(e) => Err(e),
+    }
+}
+
+fn apply_row(file_path: &str, user: User, status: RowStatus) -> Result<(), DatabaseError> {
+    match status {
+        RowStatus::Created => user.save_to_csv(file_path),
+        RowStatus::Overwritten => {
+            let mut existing = User::lookup_user(file_path, &user.username)?;
+            existing.languages = user.languages;
+            existing.discord_id = user.discord_id;
+            existing.update_user(file_path)
+        }
+        RowStatus::Merged => {
+            let mut existing = User::lookup_user(file_path, &user.username)?;
+            if existing.discord_id.is_empty() {
+                existing.discord_id = user.discord_id;
+            }
+            existing.add_language(user.languages, file_path)
+        }
+        RowStatus::Skipped | RowStatus::Invalid | RowStatus::Failed => Ok(()),
+    }
+}
+
+/// copy of the store files so an atomic import can be rolled back.
+struct Snapshot {
+    files: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl Snapshot {
+    fn take(file_path: &str) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for path in [file_path.to_string(), history_path(file_path)] {
+            let contents = match fs::read(&path) {
+                Ok(contents) => Some(contents),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            files.push((path, contents));
+        }
+        Ok(Self { files })
+    }
+
+    fn restore(self) -> io::Result<()> {
+        for (path, contents) in self.files {
+            match contents {
+                Some(contents) => rewrite_bytes(&path, &contents)?,
+                None => match fs::remove_file(&path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+fn report(options: ImportOptions, applied: bool, rows: Vec<RowResult>) -> ImportReport {
+    let count = |status| rows.iter().filter(|r| r.status == status).count();
+    ImportReport {
+        dry_run: options.dry_run,
+        mode: options.mode,
+        on_conflict: options.on_conflict,
+        applied,
+        created: count(RowStatus::Created),
+        overwritten: count(RowStatus::Overwritten),
+        merged: count(RowStatus::Merged),
+        skipped: count(RowStatus::Skipped),
+        invalid: count(RowStatus::Invalid),
+        failed: count(RowStatus::Failed),
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::path::Path;
+
+    fn store(dir: &TempDir) -> String {
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, "fork,Rust,1\nbob,Go,3,1000\n").unwrap();
+        file_path
+    }
+
+    fn options(mode: ImportMode, on_conflict: ConflictPolicy) -> ImportOptions {
+        ImportOptions {
+            dry_run: false,
+            mode,
+            on_conflict,
+        }
+    }
+
+    fn statuses(report: &ImportReport) -> Vec<RowStatus> {
+        report.rows.iter().map(|row| row.status).collect()
+    }
+
+    #[test]
+    fn csv_header_is_optional_and_extra_columns_are_refused() {
+        let rows = parse_csv("username,languages,discord_id\nfork,Rust|C,1\n\nalice\n").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].username, "alice");
+        assert!(matches!(
+            parse_csv("fork,Rust,1,extra"),
+            Err(ImportError::BadCsv { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn skip_leaves_existing_users_alone() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let rows = parse_csv("fork,C,9\nalice,Haskell,2").unwrap();
+
+        let skip = options(ImportMode::Atomic, ConflictPolicy::Skip);
+        let report = import_users(&file_path, rows, skip).unwrap();
+        assert!(report.applied);
+        assert_eq!(statuses(&report), [RowStatus::Skipped, RowStatus::Created]);
+        let fork = User::lookup_user(&file_path, "fork").unwrap();
+        assert_eq!(fork.languages, [Language::Rust]);
+        assert_eq!(fork.discord_id, "1");
+        assert!(User::lookup_user(&file_path, "alice").is_ok());
+    }
+
+    #[test]
+    fn overwrite_replaces_and_merge_adds() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let rows = parse_csv("fork,C,9").unwrap();
+        let merge = options(ImportMode::Atomic, ConflictPolicy::Merge);
+        import_users(&file_path, rows, merge).unwrap();
+        let fork = User::lookup_user(&file_path, "fork").unwrap();
+        assert_eq!(fork.languages, [Language::Rust, Language::C]);
+        assert_eq!(fork.discord_id, "1");
+
+        let rows = parse_csv("fork,Go,9").unwrap();
+        let overwrite = options(ImportMode::Atomic, ConflictPolicy::Overwrite);
+        import_users(&file_path, rows, overwrite).unwrap();
+        let fork = User::lookup_user(&file_path, "fork").unwrap();
+        assert_eq!(fork.languages, [Language::Go]);
+        assert_eq!(fork.discord_id, "9");
+    }
+
+    #[test]
+    fn atomic_import_with_a_bad_row_writes_nothing() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let before = fs::read_to_string(&file_path).unwrap();
+        // bob is soft deleted and still holds the name
+        let rows = parse_csv("alice,Haskell,2\nbob,Go,3\ncarol,Klingon,4").unwrap();
+
+        let skip = options(ImportMode::Atomic, ConflictPolicy::Skip);
+        let report = import_users(&file_path, rows, skip).unwrap();
+        assert!(!report.applied);
+        assert_eq!(
+            statuses(&report),
+            [RowStatus::Created, RowStatus::Failed, RowStatus::Invalid]
+        );
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), before);
+    }
+
+    #[test]
+    fn best_effort_import_applies_the_good_rows() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let rows = parse_csv("alice,Haskell,2\nbob,Go,3\nalice,C,5").unwrap();
+
+        let best_effort = options(ImportMode::BestEffort, ConflictPolicy::Skip);
+        let report = import_users(&file_path, rows, best_effort).unwrap();
+        assert!(report.applied);
+        assert_eq!((report.created, report.failed, report.invalid), (1, 1, 1));
+        assert_eq!(
+            User::lookup_user(&file_path, "alice").unwrap().languages,
+            [Language::Haskell]
+        );
+    }
+
+    #[test]
+    fn dry_run_only_reports() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let rows = parse_csv("alice,Haskell,2").unwrap();
+
+        let dry_run = ImportOptions {
+            dry_run: true,
+            ..options(ImportMode::Atomic, ConflictPolicy::Skip)
+        };
+        let report = import_users(&file_path, rows, dry_run).unwrap();
+        assert_eq!(report.created, 1);
+        assert!(User::lookup_user(&file_path, "alice").is_err());
+    }
+
+    #[test]
+    fn snapshot_restore_swaps_the_store_back_in() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+        let snapshot = Snapshot::take(&file_path).unwrap();
+
+        fs::write(&file_path, "mallory,C,6\n").unwrap();
+        fs::write(history_path(&file_path), "1,1,fork,Rust,1\n").unwrap();
+        snapshot.restore().unwrap();
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "fork,Rust,1\nbob,Go,3,1000\n"
+        );
+        // there was no history when the snapshot was taken
+        assert!(!Path::new(&history_path(&file_path)).exists());
+        assert!(!Path::new(&format!("{}.tmp", file_path)).exists());
+    }
+}
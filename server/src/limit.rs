@@ -0,0 +1,527 @@
+use crate::api::{header_key, identify, ApiError, Caller};
+use crate::config::{Config, RateLimit};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, MatchedPath, Request},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// probes and scrapers poll these, so they are never throttled.
+const EXEMPT: [&str; 3] = ["/healthz", "/readyz", "/metrics"];
+/// tracked ips and keys past which idle entries are dropped.
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Subject {
+    Ip(IpAddr),
+    /// by key name or token holder, never the key itself.
+    Key(String),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let rate = limit.per_minute as f64 / 60.0;
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// takes a token, or says how long until the next one.
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let rate = limit.per_minute as f64 / 60.0;
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// when failed authentications lock an ip out: `failures` of them within
+/// `window` lock it out for `duration`. 0 `failures` turns it off.
+#[derive(Debug, Clone, Copy)]
+struct Lockout {
+    failures: u64,
+    window: Duration,
+    duration: Duration,
+}
+
+impl Lockout {
+    fn configured() -> Self {
+        let config = Config::get();
+        Lockout {
+            failures: config.auth_failure_limit,
+            window: config.auth_failure_window,
+            duration: config.lockout,
+        }
+    }
+}
+
+/// failed authentications from one ip.
+struct Failures {
+    count: u64,
+    since: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Limiter {
+    buckets: HashMap<(Subject, Class), Bucket>,
+    failures: HashMap<IpAddr, Failures>,
+}
+
+static LIMITER: LazyLock<Mutex<Limiter>> = LazyLock::new(Default::default);
+
+fn limiter() -> MutexGuard<'static, Limiter> {
+    LIMITER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Limiter {
+    fn take(&mut self, subject: Subject, class: Class, now: Instant) -> Result<(), Duration> {
+        let limit = limit(class);
+        if limit.per_minute == 0 {
+            return Ok(());
+        }
+        if self.buckets.len() >= MAX_TRACKED {
+            self.forget_idle(now);
+        }
+        self.buckets
+            .entry((subject, class))
+            .or_insert_with(|| Bucket::full(limit, now))
+            .take(limit, now)
+    }
+
+    fn locked_out(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        let locked_until = self.failures.get(&ip)?.locked_until?;
+        (locked_until > now).then(|| locked_until - now)
+    }
+
+    fn auth_failed(&mut self, ip: IpAddr, lockout: Lockout, now: Instant) {
+        if lockout.failures == 0 {
+            return;
+        }
+        if self.failures.len() >= MAX_TRACKED {
+            self.forget_idle(now);
+        }
+
+        let failures = self.failures.entry(ip).or_insert(Failures {
+            count: 0,
+            since: now,
+            locked_until: None,
+        });
+        if now.duration_since(failures.since) > lockout.window {
+            failures.count = 0;
+            failures.since = now;
+        }
+        failures.count += 1;
+        if failures.count >= lockout.failures {
+            tracing::warn!(
+                %ip,
+                "locking out for {}s after {} failed authentications",
+                lockout.duration.as_secs(),
+                failures.count
+            );
+            failures.locked_until = Some(now + lockout.duration);
+            failures.count = 0;
+            failures.since = now;
+        }
+    }
+
+    fn auth_succeeded(&mut self, ip: IpAddr) {
+        self.failures.remove(&ip);
+    }
+
+    /// drops full buckets and failures that no longer count, which behave
+    /// exactly like having no entry at all.
+    fn forget_idle(&mut self, now: Instant) {
+        self.buckets.retain(|(_, class), bucket| {
+            let limit = limit(*class);
+            bucket.refill(limit, now);
+            bucket.tokens < limit.burst as f64
+        });
+        let window = Config::get().auth_failure_window;
+        self.failures.retain(|_, failures| {
+            failures.locked_until.is_some_and(|until| until > now)
+                || now.duration_since(failures.since) <= window
+        });
+    }
+}
+
+fn limit(class: Class) -> RateLimit {
+    let config = Config::get();
+    match class {
+        Class::Read => config.read_limit,
+        Class::Write => config.write_limit,
+    }
+}
+
+/// counts a failed login or refresh from `ip` towards its lockout, as a
+/// wrong key in a header is counted by [`enforce`].
+pub fn record_auth_failure(ip: IpAddr) {
+    limiter().auth_failed(ip, Lockout::configured(), Instant::now());
+}
+
+/// middleware applying the per ip and per key rate limits and the lockout
+/// after repeated failed authentications. throttled requests get a 429 with
+/// `Retry-After` before reaching a handler.
+pub async fn enforce(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    if route
+        .as_deref()
+        .is_some_and(|route| EXEMPT.contains(&route))
+    {
+        return next.run(request).await;
+    }
+
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let class = class(request.method(), route.as_deref());
+    let key = presented_key(&request, route.as_deref());
+    let now = Instant::now();
+
+    {
+        let mut limiter = limiter();
+        if let Some(ip) = ip {
+            if let Some(wait) = limiter.locked_out(ip, now) {
+                return too_many_requests(
+                    "locked_out",
+                    "too many failed authentications from this address",
+                    wait,
+                );
+            }
+            if let Err(wait) = limiter.take(Subject::Ip(ip), class, now) {
+                return too_many_requests(
+                    "rate_limited",
+                    "too many requests from this address",
+                    wait,
+                );
+            }
+        }
+    }
+
+    // resolved outside the limiter lock since a personal token means reading
+    // the token file. the handler still answers a wrong key itself; this
+    // only counts it
+    let caller = key.map(|key| identify(&key));
+    {
+        let mut limiter = limiter();
+        match caller {
+            Some(Ok(Some(caller))) => {
+                // only the admin key wipes the slate. a member's own token
+                // says nothing about guesses at the key, and letting it reset
+                // the count would allow guessing forever
+                if let (Some(ip), Caller::Admin) = (ip, &caller) {
+                    limiter.auth_succeeded(ip);
+                }
+                if let Err(wait) = limiter.take(Subject::Key(caller.name()), class, now) {
+                    return too_many_requests(
+                        "rate_limited",
+                        "too many requests with this key",
+                        wait,
+                    );
+                }
+            }
+            Some(Ok(None)) => {
+                if let Some(ip) = ip {
+                    limiter.auth_failed(ip, Lockout::configured(), now);
+                }
+            }
+            // the store is unreadable; that's no reason to lock anyone out
+            Some(Err(_)) | None => {}
+        }
+    }
+
+    next.run(request).await
+}
+
+/// middleware rejecting oversized requests before any handler sees them:
+/// 431 for headers, 422 for a path segment and 413 for the body.
+pub async fn check_size(request: Request, next: Next) -> Response {
+    let limits = Config::get().limits;
+
+    let header_bytes: usize = request
+        .headers()
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    if header_bytes > limits.max_header_bytes {
+        return ApiError::new(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "headers_too_large",
+            format!("headers may be at most {} bytes", limits.max_header_bytes),
+        )
+        .into_response();
+    }
+
+    if request
+        .uri()
+        .path()
+        .split('/')
+        .any(|segment| segment.len() > limits.max_segment_len)
+    {
+        return ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "path_segment_too_long",
+            format!(
+                "path segments may be at most {} bytes",
+                limits.max_segment_len
+            ),
+        )
+        .into_response();
+    }
+
+    let too_large = || {
+        ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "body_too_large",
+            format!("bodies may be at most {} bytes", limits.max_body_bytes),
+        )
+        .into_response()
+    };
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limits.max_body_bytes) {
+        return too_large();
+    }
+    // chunked bodies carry no length, so read up to the limit and give up
+    // past it
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, limits.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(_) => return too_large(),
+    };
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// the legacy routes write through GET; of them only the lookup reads.
+fn class(method: &Method, route: Option<&str>) -> Class {
+    let legacy_write =
+        route.is_some_and(|route| route.starts_with("/:key/") && route != "/:key/:user/");
+    if (method == Method::GET || method == Method::HEAD) && !legacy_write {
+        Class::Read
+    } else {
+        Class::Write
+    }
+}
+
+/// the key from the path on the legacy routes, from the headers elsewhere.
+fn presented_key(request: &Request, route: Option<&str>) -> Option<String> {
+    if route.is_some_and(|route| route.starts_with("/:key/")) {
+        return request.uri().path().split('/').nth(1).map(str::to_string);
+    }
+    header_key(request.headers()).map(str::to_string)
+}
+
+fn too_many_requests(code: &'static str, message: &str, wait: Duration) -> Response {
+    let secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = ApiError::new(StatusCode::TOO_MANY_REQUESTS, code, message).into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    const LIMIT: RateLimit = RateLimit {
+        per_minute: 60,
+        burst: 3,
+    };
+
+    #[test]
+    fn bucket_allows_the_burst_then_refuses() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(LIMIT, start);
+        for _ in 0..LIMIT.burst {
+            assert!(bucket.take(LIMIT, start).is_ok());
+        }
+        let wait = bucket.take(LIMIT, start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bucket_refills_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(LIMIT, start);
+        for _ in 0..LIMIT.burst {
+            bucket.take(LIMIT, start).unwrap();
+        }
+
+        // one a second at 60 a minute
+        let later = start + Duration::from_millis(1500);
+        assert!(bucket.take(LIMIT, later).is_ok());
+        let wait = bucket.take(LIMIT, later).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn bucket_never_holds_more_than_the_burst() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(LIMIT, start);
+        bucket.refill(LIMIT, start + Duration::from_secs(3600));
+        assert_eq!(bucket.tokens, LIMIT.burst as f64);
+    }
+
+    const LOCKOUT: Lockout = Lockout {
+        failures: 3,
+        window: Duration::from_secs(60),
+        duration: Duration::from_secs(900),
+    };
+
+    #[test]
+    fn repeated_failures_lock_out_until_the_lockout_ends() {
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let start = Instant::now();
+        let mut limiter = Limiter::default();
+
+        for _ in 1..LOCKOUT.failures {
+            limiter.auth_failed(ip, LOCKOUT, start);
+        }
+        assert_eq!(limiter.locked_out(ip, start), None);
+
+        limiter.auth_failed(ip, LOCKOUT, start);
+        assert_eq!(limiter.locked_out(ip, start), Some(LOCKOUT.duration));
+        assert_eq!(limiter.locked_out(ip, start + LOCKOUT.duration), None);
+        assert_eq!(
+            limiter.locked_out(IpAddr::from([192, 0, 2, 9]), start),
+            None
+        );
+    }
+
+    #[test]
+    fn failures_outside_the_window_start_over() {
+        let ip = IpAddr::from([192, 0, 2, 2]);
+        let start = Instant::now();
+        let mut limiter = Limiter::default();
+
+        for _ in 1..LOCKOUT.failures {
+            limiter.auth_failed(ip, LOCKOUT, start);
+        }
+        let later = start + LOCKOUT.window + Duration::from_secs(1);
+        limiter.auth_failed(ip, LOCKOUT, later);
+        assert_eq!(limiter.locked_out(ip, later), None);
+    }
+
+    #[test]
+    fn success_clears_failures() {
+        let ip = IpAddr::from([192, 0, 2, 3]);
+        let start = Instant::now();
+        let mut limiter = Limiter::default();
+
+        for _ in 1..LOCKOUT.failures {
+            limiter.auth_failed(ip, LOCKOUT, start);
+        }
+        limiter.auth_succeeded(ip);
+        limiter.auth_failed(ip, LOCKOUT, start);
+        assert_eq!(limiter.locked_out(ip, start), None);
+    }
+
+    #[test]
+    fn no_failure_limit_means_no_lockout() {
+        let off = Lockout {
+            failures: 0,
+            ..LOCKOUT
+        };
+        let ip = IpAddr::from([192, 0, 2, 4]);
+        let start = Instant::now();
+        let mut limiter = Limiter::default();
+
+        for _ in 0..100 {
+            limiter.auth_failed(ip, off, start);
+        }
+        assert_eq!(limiter.locked_out(ip, start), None);
+    }
+
+    async fn status(request: Request) -> StatusCode {
+        let app = axum::Router::new()
+            .route("/*path", axum::routing::any(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(check_size));
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    fn get(uri: &str) -> axum::http::request::Builder {
+        axum::http::Request::get(uri)
+    }
+
+    #[tokio::test]
+    async fn requests_within_the_limits_pass() {
+        let request = get("/v1/users/fork").body(Body::from("{}")).unwrap();
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn oversized_headers_get_431() {
+        let limits = Config::get().limits;
+        let request = get("/v1/users")
+            .header("x-padding", "a".repeat(limits.max_header_bytes))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            status(request).await,
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn long_path_segments_get_422() {
+        let limits = Config::get().limits;
+        let uri = format!("/v1/users/{}", "a".repeat(limits.max_segment_len + 1));
+        let request = get(&uri).body(Body::empty()).unwrap();
+        assert_eq!(status(request).await, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_get_413() {
+        let limits = Config::get().limits;
+        let declared = get("/v1/users")
+            .header(header::CONTENT_LENGTH, limits.max_body_bytes + 1)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status(declared).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // no content-length, as with a chunked upload
+        let undeclared = get("/v1/users")
+            .body(Body::from(vec![b'a'; limits.max_body_bytes + 1]))
+            .unwrap();
+        assert_eq!(status(undeclared).await, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::{Config, LogFormat};
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{HeaderName, Request, Response},
+    Router,
+};
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// taken from the request when the client sends one, generated otherwise,
+/// and echoed on the response either way.
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// installs the global subscriber. call once, before anything logs.
+pub fn init(config: &Config) {
+    // a bad filter is in `config.problems`; log at info until startup
+    // reports it
+    let filter = EnvFilter::try_new(&config.log_level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stdout().is_terminal());
+    match config.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+/// wraps every route in a span carrying the request id, method and route,
+/// and logs one line per response with its status and latency.
+pub fn trace(router: Router) -> Router {
+    router
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_span)
+                .on_request(())
+                .on_response(on_response)
+                .on_failure(()),
+        )
+        .layer(SetRequestIdLayer::new(REQUEST_ID, MakeRequestUuid))
+}
+
+fn make_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    // the route template, never the raw path: the legacy routes carry the
+    // api key as a path segment
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        route,
+    )
+}
+
+fn on_response(response: &Response<Body>, latency: Duration, _span: &Span) {
+    let status = response.status().as_u16();
+    let latency_ms = latency.as_millis() as u64;
+    if response.status().is_server_error() {
+        tracing::error!(status, latency_ms, "request failed");
+    } else {
+        tracing::info!(status, latency_ms, "request finished");
+    }
+}
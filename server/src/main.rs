@@ -1,19 +1,40 @@
+mod api;
+mod backup;
+mod cli;
+mod config;
+mod export;
+mod health;
+mod import;
+mod limit;
+mod logging;
+mod metrics;
+mod registration;
 mod server;
+mod session;
+#[cfg(test)]
+mod testing;
+mod tls;
+mod token;
 mod user;
 
-use axum::handler;
-
 use axum::{
-    response::Html,
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
     Router,
 };
+use axum_server::Handle;
+use config::Config;
+use futures_util::FutureExt;
 use server::delete_post_handler;
+use std::{net::SocketAddr, process::ExitCode};
 
 #[tokio::main]
-async fn main() {
-    let languages: Vec<crate::user::Language> =
-        vec![crate::user::Language::C, crate::user::Language::Rust];
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        return code;
+    }
 
     // let user = crate::user::User::create_user(
     //     Some("fork".to_owned()),
@@ -25,19 +46,230 @@ async fn main() {
     // println!("{:?}", user);
     // user.save_to_csv(crate::server::FILEPATH).unwrap();
 
+    let config = Config::get();
+    logging::init(config);
+    if !config.problems.is_empty() {
+        for problem in &config.problems {
+            tracing::error!("{}", problem);
+        }
+        tracing::error!("refusing to start until the configuration is fixed");
+        return ExitCode::FAILURE;
+    }
+
+    // held until exit so the admin commands can't change the files under us
+    let _claim = match user::claim_store(server::FILEPATH) {
+        Ok(Some(claim)) => claim,
+        Ok(None) => {
+            tracing::error!("the store is in use by another CCweb process");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            tracing::error!("failed to lock the store: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = user::create_store(server::FILEPATH) {
+        tracing::error!("failed to create the store: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    if config.session_secret.is_none() {
+        tracing::warn!(
+            "SESSION_SECRET is not set; access tokens stop working when the server restarts"
+        );
+    }
+
+    tokio::spawn(purge_deleted_users());
+
     let app = Router::new()
         .route(
             "/:key/:mode/:user/:languages/:discordid",
             get(crate::server::create_post_handler).post(crate::server::create_post_handler),
         )
         .route("/:key/:user/", get(crate::server::get_handler))
+        .route("/:key/:mode/:user", post(delete_post_handler))
+        .route("/:key/restore/:user", post(crate::server::restore_handler))
+        .route(
+            "/v1/users",
+            get(crate::api::list_users_handler).post(crate::api::create_user_handler),
+        )
+        .route(
+            "/v1/users/:name",
+            get(crate::api::get_user_handler).delete(crate::api::delete_user_handler),
+        )
+        .route(
+            "/v1/users/:name/languages",
+            post(crate::api::add_languages_handler).delete(crate::api::remove_languages_handler),
+        )
+        .route(
+            "/v1/users/:name/restore",
+            post(crate::api::restore_user_handler),
+        )
+        .route(
+            "/v1/users/:name/token",
+            post(crate::api::issue_token_handler).delete(crate::api::revoke_token_handler),
+        )
+        .route("/v1/users/:name/history", get(crate::api::history_handler))
+        .route(
+            "/v1/users/:name/revert/:version",
+            post(crate::api::revert_handler),
+        )
+        .route("/v1/auth/login", post(crate::api::login_handler))
+        .route("/v1/auth/refresh", post(crate::api::refresh_handler))
+        .route("/v1/auth/logout", post(crate::api::logout_handler))
         .route(
-            "/:key/:mode/:user",
-            get(delete_post_handler).post(delete_post_handler),
+            "/v1/admin/sessions",
+            axum::routing::delete(crate::api::revoke_sessions_handler),
+        )
+        .route("/v1/register", post(crate::api::register_handler))
+        .route(
+            "/v1/admin/registrations",
+            get(crate::api::list_registrations_handler),
+        )
+        .route(
+            "/v1/admin/registrations/:name/approve",
+            post(crate::api::approve_registration_handler),
+        )
+        .route(
+            "/v1/admin/registrations/:name/reject",
+            post(crate::api::reject_registration_handler),
+        )
+        .route(
+            "/v1/admin/invites",
+            get(crate::api::list_invites_handler).post(crate::api::create_invite_handler),
+        )
+        .route(
+            "/v1/admin/invites/:code",
+            axum::routing::delete(crate::api::revoke_invite_handler),
+        )
+        .route("/v1/export", get(crate::api::export_handler))
+        .route("/v1/admin/backup", post(crate::api::backup_handler))
+        .route("/v1/admin/backups", get(crate::api::list_backups_handler))
+        .route("/v1/admin/restore", post(crate::api::restore_handler))
+        .route("/v1/:action", post(crate::api::collection_action_handler))
+        .route("/metrics", get(crate::metrics::metrics_handler))
+        .route("/healthz", get(crate::health::healthz_handler))
+        .route("/readyz", get(crate::health::readyz_handler))
+        .layer(middleware::from_fn(crate::limit::check_size))
+        .layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+        .layer(middleware::from_fn(crate::limit::enforce))
+        .layer(middleware::from_fn(crate::metrics::track));
+    let app = logging::trace(app);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let handle = Handle::new();
+    // the peer address feeds the per ip rate limits
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = match &config.tls {
+        Some(paths) => {
+            let rustls = match tls::load(paths).await {
+                Ok(rustls) => rustls,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            tokio::spawn(tls::reload_on_change(
+                rustls.clone(),
+                paths.clone(),
+                config.tls_reload_interval,
+            ));
+            tracing::info!("listening on https://{}", addr);
+            axum_server::bind_rustls(addr, rustls)
+                .handle(handle.clone())
+                .serve(app)
+                .boxed()
+        }
+        None => {
+            tracing::info!("listening on http://{}", addr);
+            axum_server::bind(addr)
+                .handle(handle.clone())
+                .serve(app)
+                .boxed()
+        }
+    };
+
+    let shutdown = async {
+        shutdown_signal().await;
+        tracing::info!(
+            "shutting down, waiting up to {}s for in-flight requests",
+            config.shutdown_timeout.as_secs()
         );
+        handle.graceful_shutdown(None);
+        tokio::time::sleep(config.shutdown_timeout).await;
+    };
+    tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                tracing::error!("server error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        _ = shutdown => tracing::warn!("in-flight requests did not finish in time"),
+    }
+
+    // a write still running holds the store lock, so this waits for it; the
+    // guard is then kept until exit so no other write can start.
+    // there is no separate audit log to flush: the change history is synced
+    // with the store below, and the `events` log goes straight to stdout
+    let _guard = user::write_lock();
+    if let Err(e) = user::sync_to_disk(server::FILEPATH) {
+        tracing::error!("failed to flush the store: {}", e);
+        return ExitCode::FAILURE;
+    }
+    tracing::info!("stopped");
+    ExitCode::SUCCESS
+}
+
+/// resolves on ctrl-c, or SIGTERM from a process supervisor.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    println!("listening on {}", listener.local_addr().unwrap());
+/// drops soft deleted users once they are older than the retention window.
+async fn purge_deleted_users() {
+    let config = Config::get();
+    let mut interval = tokio::time::interval(config.purge_interval);
 
-    axum::serve(listener, app).await.unwrap();
+    loop {
+        interval.tick().await;
+        let purged = {
+            let _guard = user::write_lock();
+            user::User::purge_deleted(server::FILEPATH, config.retention)
+        };
+        match purged {
+            Ok(purged) if !purged.is_empty() => {
+                tracing::info!(count = purged.len(), "purged deleted users: {:?}", purged)
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("failed to purge deleted users: {}", e),
+        }
+    }
 }
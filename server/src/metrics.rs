@@ -0,0 +1,292 @@
+use crate::registration;
+use crate::server::FILEPATH;
+use crate::user::{self, Language, User};
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+
+/// upper bounds in seconds, shared by every histogram. store operations on a
+/// small csv land in the low buckets, slow disks and big imports in the high
+/// ones.
+const BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    /// observations per bucket, not cumulative; cumulated when rendered.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+struct Metrics {
+    /// by (route, method, status)
+    requests: BTreeMap<(String, String, u16), Histogram>,
+    /// by key name
+    auth_failures: BTreeMap<String, u64>,
+    /// by operation: lookup, save, rewrite
+    store: BTreeMap<&'static str, Histogram>,
+    /// by outcome: requested, approved, rejected, invited
+    registrations: BTreeMap<&'static str, u64>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    requests: BTreeMap::new(),
+    auth_failures: BTreeMap::new(),
+    store: BTreeMap::new(),
+    registrations: BTreeMap::new(),
+});
+
+fn metrics() -> std::sync::MutexGuard<'static, Metrics> {
+    METRICS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// middleware recording the count and latency of every request by route
+/// template and status.
+pub async fn track(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let seconds = started.elapsed().as_secs_f64();
+    metrics()
+        .requests
+        .entry((route, method, response.status().as_u16()))
+        .or_default()
+        .observe(seconds);
+    response
+}
+
+/// counts a rejected credential. `key` names the key that was tried, never
+/// its value.
+pub fn auth_failure(key: &str) {
+    *metrics().auth_failures.entry(key.to_string()).or_default() += 1;
+}
+
+/// counts a self-registration by what became of it.
+pub fn registration(outcome: &'static str) {
+    *metrics().registrations.entry(outcome).or_default() += 1;
+}
+
+/// runs one store operation, recording how long it took.
+pub fn time_store<T>(operation: &'static str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    let seconds = started.elapsed().as_secs_f64();
+    metrics()
+        .store
+        .entry(operation)
+        .or_default()
+        .observe(seconds);
+    result
+}
+
+/// `GET /metrics`, in the prometheus text format.
+pub async fn metrics_handler() -> Response {
+    // counted at scrape time so they can never drift from the file
+    let (users, pending) = {
+        let _guard = user::read_lock();
+        (User::all(FILEPATH), registration::pending(FILEPATH))
+    };
+
+    let mut out = String::new();
+    {
+        let metrics = metrics();
+
+        header(
+            &mut out,
+            "ccweb_http_requests_total",
+            "counter",
+            "requests served, by route template, method and status.",
+        );
+        for ((route, method, status), histogram) in &metrics.requests {
+            let labels = labels(&[
+                ("route", route),
+                ("method", method),
+                ("status", &status.to_string()),
+            ]);
+            writeln!(
+                out,
+                "ccweb_http_requests_total{{{}}} {}",
+                labels, histogram.count
+            )
+            .ok();
+        }
+
+        header(
+            &mut out,
+            "ccweb_http_request_duration_seconds",
+            "histogram",
+            "time to produce a response, by route template, method and status.",
+        );
+        for ((route, method, status), histogram) in &metrics.requests {
+            let labels = labels(&[
+                ("route", route),
+                ("method", method),
+                ("status", &status.to_string()),
+            ]);
+            write_histogram(
+                &mut out,
+                "ccweb_http_request_duration_seconds",
+                &labels,
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "ccweb_auth_failures_total",
+            "counter",
+            "rejected credentials, by the name of the key tried.",
+        );
+        for (key, count) in &metrics.auth_failures {
+            writeln!(
+                out,
+                "ccweb_auth_failures_total{{{}}} {}",
+                labels(&[("key", key)]),
+                count
+            )
+            .ok();
+        }
+
+        header(
+            &mut out,
+            "ccweb_store_operation_duration_seconds",
+            "histogram",
+            "time spent in users.csv, by operation: lookup, save or rewrite.",
+        );
+        for (operation, histogram) in &metrics.store {
+            write_histogram(
+                &mut out,
+                "ccweb_store_operation_duration_seconds",
+                &labels(&[("operation", operation)]),
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "ccweb_registrations_total",
+            "counter",
+            "self-registrations, by outcome: requested, approved, rejected or invited.",
+        );
+        for (outcome, count) in &metrics.registrations {
+            writeln!(
+                out,
+                "ccweb_registrations_total{{{}}} {}",
+                labels(&[("outcome", outcome)]),
+                count
+            )
+            .ok();
+        }
+    }
+
+    match pending {
+        Ok(pending) => {
+            header(
+                &mut out,
+                "ccweb_pending_registrations",
+                "gauge",
+                "registrations waiting for approval.",
+            );
+            writeln!(out, "ccweb_pending_registrations {}", pending.len()).ok();
+        }
+        Err(e) => tracing::warn!("could not count pending registrations for metrics: {}", e),
+    }
+
+    match users {
+        Ok(users) => {
+            header(&mut out, "ccweb_users", "gauge", "live users in the store.");
+            writeln!(out, "ccweb_users {}", users.len()).ok();
+
+            header(
+                &mut out,
+                "ccweb_users_by_language",
+                "gauge",
+                "live users knowing each language.",
+            );
+            for language in Language::ALL {
+                let count = users
+                    .iter()
+                    .filter(|u| u.languages.contains(language))
+                    .count();
+                writeln!(
+                    out,
+                    "ccweb_users_by_language{{{}}} {}",
+                    labels(&[("language", &language.to_string())]),
+                    count
+                )
+                .ok();
+            }
+        }
+        Err(e) => tracing::warn!("could not count users for metrics: {}", e),
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).ok();
+    writeln!(out, "# TYPE {} {}", name, kind).ok();
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+        cumulative += count;
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"{}\"}} {}",
+            name, labels, bound, cumulative
+        )
+        .ok();
+    }
+    writeln!(
+        out,
+        "{}_bucket{{{},le=\"+Inf\"}} {}",
+        name, labels, histogram.count
+    )
+    .ok();
+    writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum).ok();
+    writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count).ok();
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,376 @@
+use crate::config::Config;
+use crate::metrics;
+use crate::user::{rewrite, DatabaseError, User};
+use protocol::{Invite, Registration};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// registrations waiting for approval live in `users.pending.csv`, as a user
+/// row followed by the time it was requested. they stay out of `users.csv`
+/// until approved, so nothing that reads the store ever sees them.
+pub fn pending_path(file_path: &str) -> String {
+    sidecar(file_path, "pending")
+}
+
+/// invite codes live in `users.invites.csv`, one `code,uses_left,expires_at`
+/// line each; an `expires_at` of 0 never expires.
+pub fn invites_path(file_path: &str) -> String {
+    sidecar(file_path, "invites")
+}
+
+fn sidecar(file_path: &str, name: &str) -> String {
+    match file_path.strip_suffix(".csv") {
+        Some(stem) => format!("{}.{}.csv", stem, name),
+        None => format!("{}.{}", file_path, name),
+    }
+}
+
+fn read_lines(path: &str) -> io::Result<Vec<String>> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file).lines().collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn pending_from_csv_line(line: &str) -> Option<Registration> {
+    let (user, requested_at) = line.rsplit_once(',')?;
+    Some(Registration {
+        user: User::from_csv_line(user)?.into(),
+        requested_at: requested_at.trim().parse().ok()?,
+    })
+}
+
+fn pending_to_csv_line(user: &User, requested_at: u64) -> String {
+    format!("{},{}", user.to_csv_line(), requested_at)
+}
+
+/// the approval queue, oldest first.
+pub fn pending(file_path: &str) -> io::Result<Vec<Registration>> {
+    Ok(read_lines(&pending_path(file_path))?
+        .iter()
+        .filter_map(|line| pending_from_csv_line(line))
+        .collect())
+}
+
+/// whether `username` is waiting in the approval queue.
+pub fn is_pending(file_path: &str, username: &str) -> io::Result<bool> {
+    Ok(pending(file_path)?
+        .iter()
+        .any(|registration| registration.user.username == username))
+}
+
+/// queues `user` for approval. the name has to be free both in the store and
+/// in the queue.
+pub fn enqueue(file_path: &str, user: &User) -> Result<Registration, DatabaseError> {
+    if User::exists(file_path, &user.username)? {
+        return Err(DatabaseError::UserAlreadyExists);
+    }
+    let mut lines = read_lines(&pending_path(file_path))?;
+    let queued = lines.iter().filter_map(|line| pending_from_csv_line(line));
+    let mut count = 0;
+    for registration in queued {
+        if registration.user.username == user.username {
+            return Err(DatabaseError::UserAlreadyExists);
+        }
+        count += 1;
+    }
+    let max = Config::get().limits.max_pending;
+    if count >= max {
+        return Err(DatabaseError::QueueFull { max });
+    }
+
+    let requested_at = now();
+    lines.push(pending_to_csv_line(user, requested_at));
+    rewrite(&pending_path(file_path), &lines)?;
+    Ok(Registration {
+        user: user.clone().into(),
+        requested_at,
+    })
+}
+
+/// takes `username` out of the queue, handing back what was queued. the
+/// queue is only rewritten once `then` has succeeded with it, so a failed
+/// approval leaves the registration waiting.
+fn dequeue<T>(
+    file_path: &str,
+    username: &str,
+    then: impl FnOnce(&Registration) -> Result<T, DatabaseError>,
+) -> Result<T, DatabaseError> {
+    let mut lines = read_lines(&pending_path(file_path))?;
+    let index = lines
+        .iter()
+        .position(|line| pending_from_csv_line(line).is_some_and(|r| r.user.username == username))
+        .ok_or(DatabaseError::RegistrationNotFound)?;
+    let registration =
+        pending_from_csv_line(&lines[index]).expect("the line parsed in the search above");
+
+    let result = then(&registration)?;
+    lines.remove(index);
+    rewrite(&pending_path(file_path), &lines)?;
+    Ok(result)
+}
+
+/// creates the queued user. fails, leaving it queued, if the name was taken
+/// in the meantime or the store is full.
+pub fn approve(file_path: &str, username: &str) -> Result<User, DatabaseError> {
+    dequeue(file_path, username, |registration| {
+        let queued = &registration.user;
+        let user = User::create_user(
+            Some(queued.username.clone()),
+            Some(queued.languages.clone()),
+            Some(queued.discord_id.clone()),
+        )?;
+        user.save_to_csv(file_path)?;
+        Ok(user)
+    })
+}
+
+pub fn reject(file_path: &str, username: &str) -> Result<Registration, DatabaseError> {
+    dequeue(file_path, username, |registration| Ok(registration.clone()))
+}
+
+fn invite_from_csv_line(line: &str) -> Option<Invite> {
+    let mut fields = line.split(',');
+    let code = fields.next()?.to_string();
+    let uses_left = fields.next()?.parse().ok()?;
+    let expires_at = fields.next()?.parse().ok()?;
+    Some(Invite {
+        code,
+        uses_left,
+        expires_at: (expires_at != 0).then_some(expires_at),
+    })
+}
+
+fn invite_to_csv_line(invite: &Invite) -> String {
+    format!(
+        "{},{},{}",
+        invite.code,
+        invite.uses_left,
+        invite.expires_at.unwrap_or(0)
+    )
+}
+
+fn is_live(invite: &Invite, now: u64) -> bool {
+    invite.uses_left > 0 && invite.expires_at.is_none_or(|at| at > now)
+}
+
+/// invites that can still be used. spent and expired ones are dropped the
+/// next time the file is written.
+pub fn invites(file_path: &str) -> io::Result<Vec<Invite>> {
+    let now = now();
+    Ok(read_lines(&invites_path(file_path))?
+        .iter()
+        .filter_map(|line| invite_from_csv_line(line))
+        .filter(|invite| is_live(invite, now))
+        .collect())
+}
+
+fn save_invites(file_path: &str, invites: &[Invite]) -> io::Result<()> {
+    let lines: Vec<String> = invites.iter().map(invite_to_csv_line).collect();
+    rewrite(&invites_path(file_path), &lines)
+}
+
+/// when an invite made now for `expires_in` seconds runs out, or `None` if
+/// that is past what a timestamp can hold.
+pub fn expiry(expires_in: u64) -> Option<u64> {
+    now().checked_add(expires_in)
+}
+
+pub fn create_invite(file_path: &str, uses: u32, expires_at: Option<u64>) -> io::Result<Invite> {
+    let invite = Invite {
+        code: format!("inv_{}", uuid::Uuid::new_v4().simple()),
+        uses_left: uses,
+        expires_at,
+    };
+    let mut live = invites(file_path)?;
+    live.push(invite.clone());
+    save_invites(file_path, &live)?;
+    Ok(invite)
+}
+
+/// false when there was no such live invite.
+pub fn revoke_invite(file_path: &str, code: &str) -> io::Result<bool> {
+    let mut live = invites(file_path)?;
+    let before = live.len();
+    live.retain(|invite| invite.code != code);
+    if live.len() == before {
+        return Ok(false);
+    }
+    save_invites(file_path, &live)?;
+    Ok(true)
+}
+
+/// whether `code` would admit a registration right now.
+pub fn invite_is_valid(file_path: &str, code: &str) -> io::Result<bool> {
+    Ok(invites(file_path)?.iter().any(|invite| invite.code == code))
+}
+
+/// uses up one registration of `code`. call only after the registration it
+/// admitted has been saved.
+pub fn redeem_invite(file_path: &str, code: &str) -> io::Result<()> {
+    let mut live = invites(file_path)?;
+    if let Some(invite) = live.iter_mut().find(|invite| invite.code == code) {
+        invite.uses_left -= 1;
+    }
+    live.retain(|invite| invite.uses_left > 0);
+    save_invites(file_path, &live)
+}
+
+/// records what became of a registration, as a metric and as an event on
+/// the `events` log target for anything that wants to react to it. `by` is
+/// who acted: the registrant, or the admin's caller name.
+pub fn emit(outcome: &'static str, username: &str, by: &str) {
+    metrics::registration(outcome);
+    tracing::info!(
+        target: "events",
+        event = %format!("registration_{}", outcome),
+        user = %username,
+        by = %by,
+        "registration {}",
+        outcome
+    );
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use protocol::Language;
+    use std::fs;
+
+    fn store(dir: &TempDir) -> String {
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, "fork,Rust,1\n").unwrap();
+        file_path
+    }
+
+    fn user(username: &str) -> User {
+        User::create_user(
+            Some(username.to_string()),
+            Some(vec![Language::Haskell]),
+            Some("7".to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn registrations_wait_in_order_until_approved() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        enqueue(&file_path, &user("alice")).unwrap();
+        enqueue(&file_path, &user("bob")).unwrap();
+        let queued: Vec<String> = pending(&file_path)
+            .unwrap()
+            .into_iter()
+            .map(|registration| registration.user.username)
+            .collect();
+        assert_eq!(queued, ["alice", "bob"]);
+        assert!(!User::exists(&file_path, "alice").unwrap());
+
+        let approved = approve(&file_path, "alice").unwrap();
+        assert_eq!(approved.languages, [Language::Haskell]);
+        assert!(User::lookup_user(&file_path, "alice").is_ok());
+        assert_eq!(pending(&file_path).unwrap().len(), 1);
+
+        assert_eq!(reject(&file_path, "bob").unwrap().user.username, "bob");
+        assert!(pending(&file_path).unwrap().is_empty());
+        assert!(!User::exists(&file_path, "bob").unwrap());
+    }
+
+    #[test]
+    fn a_name_can_only_be_taken_once() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        assert!(matches!(
+            enqueue(&file_path, &user("fork")),
+            Err(DatabaseError::UserAlreadyExists)
+        ));
+        enqueue(&file_path, &user("alice")).unwrap();
+        assert!(matches!(
+            enqueue(&file_path, &user("alice")),
+            Err(DatabaseError::UserAlreadyExists)
+        ));
+        assert!(is_pending(&file_path, "alice").unwrap());
+        assert!(!is_pending(&file_path, "fork").unwrap());
+        assert!(matches!(
+            approve(&file_path, "nobody"),
+            Err(DatabaseError::RegistrationNotFound)
+        ));
+    }
+
+    #[test]
+    fn failed_approval_leaves_the_registration_queued() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        enqueue(&file_path, &user("alice")).unwrap();
+        // taken by someone else while alice waited
+        fs::write(&file_path, "fork,Rust,1\nalice,C,2\n").unwrap();
+        assert!(matches!(
+            approve(&file_path, "alice"),
+            Err(DatabaseError::UserAlreadyExists)
+        ));
+        assert_eq!(pending(&file_path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invites_run_out_after_their_uses() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let invite = create_invite(&file_path, 2, None).unwrap();
+        assert_eq!(invite.expires_at, None);
+        for _ in 0..2 {
+            assert!(invite_is_valid(&file_path, &invite.code).unwrap());
+            redeem_invite(&file_path, &invite.code).unwrap();
+        }
+        assert!(!invite_is_valid(&file_path, &invite.code).unwrap());
+        assert!(invites(&file_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn expired_invites_are_not_valid() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        fs::write(
+            invites_path(&file_path),
+            format!("inv_old,3,{}\ninv_new,3,{}\n", now() - 1, now() + 60),
+        )
+        .unwrap();
+        assert!(!invite_is_valid(&file_path, "inv_old").unwrap());
+        assert!(invite_is_valid(&file_path, "inv_new").unwrap());
+
+        let invite = create_invite(&file_path, 1, expiry(60)).unwrap();
+        assert!(invite.expires_at.is_some_and(|at| at >= now() + 59));
+    }
+
+    #[test]
+    fn expiries_past_the_end_of_time_are_refused() {
+        assert_eq!(expiry(u64::MAX), None);
+        assert!(expiry(60).is_some());
+    }
+
+    #[test]
+    fn revoked_invites_are_gone() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let invite = create_invite(&file_path, 1, None).unwrap();
+        assert!(revoke_invite(&file_path, &invite.code).unwrap());
+        assert!(!revoke_invite(&file_path, &invite.code).unwrap());
+        assert!(!invite_is_valid(&file_path, &invite.code).unwrap());
+    }
+}
@@ -1,8 +1,16 @@
-use crate::user::{self, Language, User};
-use axum::{extract::Path, response::Html, routing::get, Form, Json, Router};
-use serde::{Deserialize, Serialize};
-use std::{env, error::Error, fmt, str::FromStr, vec};
-use tokio::net::lookup_host;
+use crate::api::ApiError;
+use crate::config::Config;
+use crate::user::{self, DatabaseError, Language, User};
+use axum::{
+    extract::Path,
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use protocol::CommandMode;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{error::Error, fmt, str::FromStr, vec};
 
 pub const FILEPATH: &str = "./users.csv";
 
@@ -15,14 +23,6 @@ struct PathParams {
     discordid: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-enum CommandMode {
-    Create,
-    Destroy,
-    AppendLanguage,
-    RemoveLanguage,
-}
-
 #[derive(Debug)]
 enum AuthError {
     InvalidApiKey,
@@ -39,33 +39,27 @@ impl fmt::Display for AuthError {
 impl Error for AuthError {}
 
 pub async fn get_handler(Path(param): Path<(String, String)>) -> Json<String> {
-    println!("get handler was called");
-
     let params = PathParams::from_get_list(axum::extract::Path(param.clone()));
 
     match authenticate(params.as_ref().unwrap().key.clone().unwrap()) {
-        Ok(_) => {
-            println!(
-                "key {} accepted",
-                params.as_ref().unwrap().key.clone().unwrap()
-            );
-        }
+        Ok(_) => {}
         Err(e) => {
             let content = format!(
                 "<h1>Invalid key: {}</1>",
                 params.unwrap().key.clone().unwrap()
             );
-            println!("{:}", e);
+            tracing::warn!("{}", e);
             // return Html(content.to_owned());
             return Json(content);
         }
     }
 
+    let _guard = user::read_lock();
     let user: Option<User> =
         match User::lookup_user(FILEPATH, &params.unwrap().user.unwrap().clone()) {
             Ok(user) => Some(user),
             Err(e) => {
-                println!("Error: {:?}", e);
+                tracing::debug!("lookup failed: {:?}", e);
                 None
             }
         };
@@ -84,7 +78,7 @@ pub async fn get_handler(Path(param): Path<(String, String)>) -> Json<String> {
 
     let json_content = match user {
         Some(user) => {
-            println!("served user: {:?}", user);
+            tracing::debug!(user = %user.username, "served user");
             format!(r#"REQUESTED USER: {:?}"#, serde_json::to_string(&user))
         }
         None => {
@@ -92,24 +86,97 @@ pub async fn get_handler(Path(param): Path<(String, String)>) -> Json<String> {
         }
     };
 
-    return Json(json_content);
+    Json(json_content)
 }
 
+/// the five segment route. a create or append that breaks the configured
+/// limits gets a structured 422 instead of the usual message and nothing is
+/// written.
 pub async fn create_post_handler(
+    method: Method,
+    Path(param): Path<(String, String, String, String, String)>,
+) -> Response {
+    let (key, mode, username, languages, discord_id) = &param;
+    if key_matches(key) {
+        if let Ok(mode) = mode.parse::<CommandMode>() {
+            // a link preview or crawler following a GET must never delete
+            if mode == CommandMode::Destroy && method == Method::GET {
+                return ApiError::new(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "method_not_allowed",
+                    "deleting a user needs a POST",
+                )
+                .into_response();
+            }
+            if let Err(e) = check_limits(mode, username, languages, discord_id) {
+                tracing::warn!("rejected {:?} for user {:?}: {}", mode, username, e);
+                return ApiError::from(e).into_response();
+            }
+        }
+    }
+    create_or_destroy(Path(param)).await.into_response()
+}
+
+fn check_limits(
+    mode: CommandMode,
+    username: &str,
+    languages: &str,
+    discord_id: &str,
+) -> Result<(), DatabaseError> {
+    let max_languages = Config::get().limits.max_languages;
+    let too_many_languages = DatabaseError::TooManyLanguages { max: max_languages };
+    // unknown languages still become BadLanguage further on; only the count
+    // matters here
+    let requested: Vec<&str> = languages.split('|').filter(|l| !l.is_empty()).collect();
+
+    match mode {
+        CommandMode::Create => {
+            if requested.len() > max_languages {
+                return Err(too_many_languages);
+            }
+            User::validate(username, &[], discord_id)?;
+
+            let _guard = user::read_lock();
+            let max = Config::get().limits.max_users;
+            if User::count(FILEPATH)? >= max {
+                return Err(DatabaseError::TooManyUsers { max });
+            }
+        }
+        CommandMode::AppendLanguage => {
+            let _guard = user::read_lock();
+            // a missing user is reported by the handler as before
+            let Ok(user) = User::lookup_user(FILEPATH, username) else {
+                return Ok(());
+            };
+            let mut known = user.languages;
+            for language in requested {
+                let language = Language::from_str(language).unwrap_or(Language::BadLanguage);
+                if !known.contains(&language) {
+                    known.push(language);
+                }
+            }
+            if known.len() > max_languages {
+                return Err(too_many_languages);
+            }
+        }
+        CommandMode::Destroy | CommandMode::RemoveLanguage => {}
+    }
+    Ok(())
+}
+
+async fn create_or_destroy(
     Path(param): Path<(String, String, String, String, String)>,
 ) -> Json<String> /*Result<Result<Html<String>, Json<String>>, Box<dyn std::error::Error>>*/ {
     let params = match PathParams::from_post_list(axum::extract::Path(param.clone())) {
         Ok(params) => params,
         Err(e) => {
-            println!("{:?}, {:?}", e, param);
+            tracing::warn!("bad params: {}", e);
             // let html = format!("bad params: {:?}", param);
             let json = format!("bad params: {:?}", param);
             // return Html(html);
             return Json(json);
         }
     };
-    println!("{:?}", params);
-
     let languages = match parse_languages(&params.languages.unwrap()) {
         Ok(languages) => languages,
         Err(_) => {
@@ -119,22 +186,21 @@ pub async fn create_post_handler(
     };
 
     match authenticate(params.key.clone().unwrap()) {
-        Ok(_) => {
-            println!("key {} accepted", params.key.clone().unwrap());
-        }
+        Ok(_) => {}
         Err(e) => {
             // let html = format!("<h1>Invalid key: {}</1>", params.key.clone().unwrap());
             let json = format!("Error: invalid key: {:?}", params.key.clone().unwrap());
-            println!("{:}", e);
+            tracing::warn!("{}", e);
             // return Html(html);
             return Json(json);
         }
     }
 
+    let _guard = user::write_lock();
     match params.mode {
         Some(mode) => match mode {
             CommandMode::Create => {
-                let user = match user::User::create_user(
+                match user::User::create_user(
                     params.user.clone(),
                     Some(languages),
                     params.discordid,
@@ -142,12 +208,7 @@ pub async fn create_post_handler(
                     Ok(user) => {
                         match user.save_to_csv(FILEPATH) {
                             Ok(_) => {
-                                println!("successfully created user {:?}: {:?}", params.user, user);
-                                let html = format!(
-                                    "<h1>Successfully created user {:?}: {:?}</h1>",
-                                    params.user,
-                                    serde_json::to_string(&user)
-                                );
+                                tracing::info!(user = %user.username, "created user");
                                 let json = format!(
                                     "Successfully created user: {:?}\n{:?}",
                                     params.user,
@@ -157,252 +218,245 @@ pub async fn create_post_handler(
                                 // return  Ok(Err(Json(json)));
                                 // return Ok(Ok(Html(html)));
                                 // return Html(html);
-                                return Json(json);
+                                Json(json)
                             }
                             Err(e) => {
-                                println!("failed to create user {:?}: {:?}", params.user, e);
-                                let html = format!(
-                                    "<h1>Failed to create user {:?}: {:?}</h1>",
-                                    params.user, e
-                                );
+                                tracing::error!("failed to create user {:?}: {:?}", params.user, e);
                                 let json =
                                     format!("Failed to create user {:?}: {:?}", params.user, e);
 
                                 //return Ok(Err(Json(json)));
                                 // return Ok(Ok(Html(html)));
                                 // return Html(html);
-                                return Json(json);
+                                Json(json)
                             }
                         }
                     }
                     Err(e) => {
-                        println!("failed to create user {:?}: {:?}", params.user, e);
-                        let html =
-                            format!("<h1>Failed to create user {:?}: {:?}</h1>", params.user, e);
+                        tracing::warn!("failed to create user {:?}: {:?}", params.user, e);
                         let json = format!("Failed to create user {:?}: {:?}", params.user, e);
 
                         //return Ok(Err(Json(json)));
                         // return Ok(Ok(Html(html)));
                         // return Html(html);
-                        return Json(json);
+                        Json(json)
                     }
-                };
+                }
             }
             CommandMode::Destroy => {
-                let user = match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
+                match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
                     Ok(user) => {
                         match user::User::remove_user(FILEPATH, &user.username) {
                             Ok(_) => {
-                                println!("Successfully deleted user {:?}", user);
-                                let html = format!(
-                                    "<h1>Successfully deleted user: {:?}</h1>",
-                                    params.user
-                                );
+                                tracing::info!(user = %user.username, "deleted user");
                                 let json =
                                     format!("Deleted user:\n{:?}", serde_json::to_string(&user));
                                 // return Ok(Err(Json(content)));
                                 // return Ok(Ok(Html(html)));
                                 // return Html(html);
-                                return Json(json);
+                                Json(json)
                             }
                             Err(e) => {
-                                println!("error: failed to delete user {:?}", user);
-                                let html = format!(
-                                    "<h1>Failed to delete user {:?}: {e}</h1>",
-                                    params.user
-                                );
+                                tracing::error!(user = %user.username, "failed to delete user: {}", e);
                                 let json = format!("Failed to delete user {:?}, {e}", params.user);
                                 // return Ok(Err(Json(content)));
                                 // return Ok(Ok(Html(html)));
                                 // return Html(html);
-                                return Json(json);
+                                Json(json)
                             }
                         }
                     }
                     Err(e) => {
-                        let html = format!("<h1>Could not find user: {:?}</h1>", e);
                         let json = format!("Could not find user: {:?}", e);
                         // return Ok(Err(Json(json)))
                         // return Ok(Ok(Html(html)));
                         // return Html(html);
-                        return Json(json);
+                        Json(json)
                     }
-                };
+                }
             }
             CommandMode::AppendLanguage => {
-                let mut user =
-                    match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
-                        Ok(mut user) => {
-                            match user::User::add_language(&mut user, languages.clone(), FILEPATH) {
-                                Ok(_) => {
-                                    println!(
-                                        "Successfully appended languages {:?} to user {:?}",
-                                        languages, params.user
-                                    );
-                                    let html = format!(
-                                    "<h1>Successfully appended languages {:?} to user: {:?}</h1>",
-                                    languages, params.user
-                                    );
-                                    let json = format!(
-                                        "Successfully appended languages {:?} to user: {:?}",
-                                        languages,
-                                        serde_json::to_string(&user)
-                                    );
-                                    // return Ok(Err(Json(content)));
-                                    // return Ok(Ok(Html(html)));
-                                    // return Html(html);
-                                    return Json(json);
-                                }
-                                Err(e) => {
-                                    let html = format!(
-                                        "<h1>Failed to append languages {:?}: {:?}</h1>",
-                                        languages, e
-                                    );
-                                    let json = format!(
-                                        "Failed to append languages {:?}: {:?}",
-                                        languages, e
-                                    );
-                                    // return Ok(Err(Json(json)))
-                                    // return Ok(Ok(Html(html)));
-                                    // return Html(html);
-                                    return Json(json);
-                                }
+                match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
+                    Ok(mut user) => {
+                        match user::User::add_language(&mut user, languages.clone(), FILEPATH) {
+                            Ok(_) => {
+                                tracing::info!(
+                                    user = %user.username,
+                                    "added languages {:?}",
+                                    languages
+                                );
+                                let json = format!(
+                                    "Successfully appended languages {:?} to user: {:?}",
+                                    languages,
+                                    serde_json::to_string(&user)
+                                );
+                                // return Ok(Err(Json(content)));
+                                // return Ok(Ok(Html(html)));
+                                // return Html(html);
+                                Json(json)
+                            }
+                            Err(e) => {
+                                let json =
+                                    format!("Failed to append languages {:?}: {:?}", languages, e);
+                                // return Ok(Err(Json(json)))
+                                // return Ok(Ok(Html(html)));
+                                // return Html(html);
+                                Json(json)
                             }
                         }
-                        Err(e) => {
-                            let html = format!("<h1>Could not find user: {:?}</h1>", e);
-                            let json = format!("Could not find user: {:?}", e);
-                            return Json(json);
-                            // return Ok(Ok(Html(html)));
-                            // return Html(html);
-                        }
-                    };
+                    }
+                    Err(e) => {
+                        let json = format!("Could not find user: {:?}", e);
+                        Json(json)
+                        // return Ok(Ok(Html(html)));
+                        // return Html(html);
+                    }
+                }
             }
             CommandMode::RemoveLanguage => {
-                let mut user =
-                    match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
-                        Ok(mut user) => {
-                            match user::User::remove_language(
-                                &mut user,
-                                languages.clone(),
-                                FILEPATH,
-                            ) {
-                                Ok(_) => {
-                                    println!(
-                                        "Successfully removed languages {:?} from user {:?}",
-                                        languages, params.user
-                                    );
-                                    let html = format!(
-                                    "<h1>Successfully removed languages {:?} from user: {:?}</h1>",
-                                    languages, params.user
-                                    );
-                                    let json = format!(
-                                        "Successfully removed languages {:?} from user: {:?}",
-                                        languages,
-                                        serde_json::to_string(&user)
-                                    );
-                                    // return Ok(Err(Json(content)));
-                                    // return Ok(Ok(Html(html)));
-                                    // return Html(html);
-                                    return Json(json);
-                                }
-                                Err(e) => {
-                                    let html = format!(
-                                        "<h1>Failed to remove languages {:?}: {:?}</h1>",
-                                        languages, e
-                                    );
-                                    let json = format!(
-                                        "Failed to remove languages {:?}: {:?}",
-                                        languages, e
-                                    );
-                                    // return Ok(Err(Json(json)))
-                                    // return Ok(Ok(Html(html)));
-                                    // return Html(html);
-                                    return Json(json);
-                                }
+                match user::User::lookup_user(FILEPATH, &params.user.clone().unwrap()) {
+                    Ok(mut user) => {
+                        match user::User::remove_language(&mut user, languages.clone(), FILEPATH) {
+                            Ok(_) => {
+                                tracing::info!(
+                                    user = %user.username,
+                                    "removed languages {:?}",
+                                    languages
+                                );
+                                let json = format!(
+                                    "Successfully removed languages {:?} from user: {:?}",
+                                    languages,
+                                    serde_json::to_string(&user)
+                                );
+                                // return Ok(Err(Json(content)));
+                                // return Ok(Ok(Html(html)));
+                                // return Html(html);
+                                Json(json)
+                            }
+                            Err(e) => {
+                                let json =
+                                    format!("Failed to remove languages {:?}: {:?}", languages, e);
+                                // return Ok(Err(Json(json)))
+                                // return Ok(Ok(Html(html)));
+                                // return Html(html);
+                                Json(json)
                             }
                         }
-                        Err(e) => {
-                            let html = format!("<h1>Could not find user: {:?}</h1>", e);
-                            let json = format!("Could not find user: {:?}", e);
-                            // return Ok(Err(Json(json)))
-                            // return Ok(Ok(Html(html)));
-                            // return Html(html);
-                            return Json(json);
-                        }
-                    };
+                    }
+                    Err(e) => {
+                        let json = format!("Could not find user: {:?}", e);
+                        // return Ok(Err(Json(json)))
+                        // return Ok(Ok(Html(html)));
+                        // return Html(html);
+                        Json(json)
+                    }
+                }
             }
         },
         None => {
-            let html = format!("<h1>Invalid mode: {:?}</h1>", params.mode);
             let json = format!("Invalid mode: {:?}", params.mode);
             // return Ok(Err(Json(json)))
             // return Ok(Ok(Html(html)));
             // return Html(html);
-            return Json(json);
+            Json(json)
         }
     }
-
-    let json = format!("this is json.");
-    let html = format!("<h1>this is html. you reached the bottom of the function.</h1>"); // you should never see this
-
-    // Ok(Err(Json(json)))
-    // Ok(Ok(Html(html)))
-    // Html(html)
-    return Json(json);
 }
 
 pub async fn delete_post_handler(Path(param): Path<(String, String, String)>) -> Json<String> {
-    let placeholder: String = String::from("monkey!");
-
-    let key = param.0;
-    let user = param.1;
+    let (key, mode, user) = param;
 
     match authenticate(key.clone()) {
-        Ok(_) => {
-            println!("key {} accepted", key);
-        }
+        Ok(_) => {}
         Err(e) => {
-            // let html = format!("<h1>Invalid key: {}</1>", params.key.clone().unwrap());
             let json = format!("Error: invalid key: {:?}", key);
-            println!("{:}", e);
-            // return Html(html);
+            tracing::warn!("{}", e);
             return Json(json);
         }
     }
 
-    match user::User::lookup_user(FILEPATH, &user) {
-        Ok(_) => {}
-        Err(_) => {
-            let json = format!("USER {} NOT FOUND!", user);
+    match mode.parse() {
+        Ok(CommandMode::Destroy) => {}
+        _ => {
+            let json = format!("Invalid mode: {:?}", mode);
             return Json(json);
         }
     }
 
+    let _guard = user::write_lock();
     match user::User::remove_user(FILEPATH, &user) {
         Ok(_) => {
-            let json: String = format!("USER {} SUCCESSFULLY DELETED!", user);
-            return Json(json);
+            let json: String = format!(
+                "USER {} SUCCESSFULLY DELETED! (restorable for {} days)",
+                user,
+                Config::get().retention.as_secs() / 86400
+            );
+            Json(json)
+        }
+        Err(DatabaseError::UserNotFound) => {
+            let json = format!("USER {} NOT FOUND!", user);
+            Json(json)
         }
         Err(e) => {
             let json: String = format!("FAILED TO DELETE USER {}: {}", user, e);
-            return Json(json);
+            Json(json)
         }
     }
 }
 
-fn authenticate(key: String) -> Result<(), Box<dyn Error>> {
-    dotenv::dotenv().ok();
+pub async fn restore_handler(Path(param): Path<(String, String)>) -> Json<String> {
+    let (key, user) = param;
 
-    let api_key = env::var("API_KEY").expect("API_KEY not set in .env file");
+    match authenticate(key.clone()) {
+        Ok(_) => {}
+        Err(e) => {
+            let json = format!("Error: invalid key: {:?}", key);
+            tracing::warn!("{}", e);
+            return Json(json);
+        }
+    }
+
+    let _guard = user::write_lock();
+    match user::User::restore_user(FILEPATH, &user) {
+        Ok(user) => {
+            tracing::info!(user = %user.username, "restored user");
+            let json = format!("Restored user:\n{:?}", serde_json::to_string(&user));
+            Json(json)
+        }
+        Err(e) => {
+            let json = format!("FAILED TO RESTORE USER {}: {}", user, e);
+            Json(json)
+        }
+    }
+}
 
-    if key == api_key {
+pub fn authenticate(key: String) -> Result<(), Box<dyn Error>> {
+    if key_matches(&key) {
         Ok(())
     } else {
+        crate::metrics::auth_failure("API_KEY");
         Err(Box::new(AuthError::InvalidApiKey))
     }
 }
 
+/// whether `key` is the configured api key, in time that doesn't depend on
+/// how much of it is right.
+pub fn key_matches(key: &str) -> bool {
+    let api_key = &Config::get().api_key;
+    // an unset key must never match an empty one
+    !api_key.is_empty() && constant_time_eq(key.as_bytes(), api_key.as_bytes())
+}
+
+/// compares digests rather than the keys themselves so neither the position
+/// of the first wrong byte nor the key's length shows in the timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter()
+        .zip(b.iter())
+        .fold(0, |diff, (x, y)| diff | (x ^ y))
+        == 0
+}
+
 impl PathParams {
     fn from_get_list(
         Path(params): Path<(String, String)>,
@@ -436,58 +490,59 @@ impl PathParams {
     }
 }
 
-impl FromStr for CommandMode {
-    type Err = ParseCommandModeError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "c" => Ok(CommandMode::Create),
-            "d" => Ok(CommandMode::Destroy),
-            "a" => Ok(CommandMode::AppendLanguage),
-            "r" => Ok(CommandMode::RemoveLanguage),
-            _ => Err(ParseCommandModeError),
-        }
-    }
-}
-
-#[derive(Debug)]
-struct ParseCommandModeError;
-
-impl fmt::Display for ParseCommandModeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Invalid command mode")
-    }
-}
-
-impl std::error::Error for ParseCommandModeError {}
-
-fn parse_languages(languages_str: &str) -> Result<Vec<Language>, &str> {
-    let mut languages_strs = Vec::new();
+pub fn parse_languages(languages_str: &str) -> Result<Vec<Language>, &str> {
     let mut languages = Vec::new();
     for language in languages_str.split('|') {
-        if language != "" {
-            languages_strs.push(language);
-            println!("{}", language)
+        if language.is_empty() {
+            continue;
         }
-    }
-    for language in languages_strs {
-        let language = match Language::from_str(language) {
+        match Language::from_str(language) {
             Ok(lang) => languages.push(lang),
             Err(_) => {
-                println!("{:?}", language);
+                tracing::debug!("unknown language {:?}", language);
                 return Err(language);
             }
-        };
-        println!("{:?}", language)
+        }
     }
-    // for language in languages_strs {
-    //     match Language::from_str(language) {
-    //         Ok(lang) => languages.push(lang),
-    //         Err(_) => {
-    //             println!("{:?}", language);
-    //             return Err(language);
-    //         }
-    //     }
-    // }5
     Ok(languages)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_past_the_limits_are_refused() {
+        let limits = Config::get().limits;
+        let languages = vec!["rust"; limits.max_languages + 1].join("|");
+        assert!(matches!(
+            check_limits(CommandMode::Create, "fork", &languages, "1"),
+            Err(DatabaseError::TooManyLanguages { .. })
+        ));
+
+        let username = "a".repeat(limits.max_username_len + 1);
+        assert!(matches!(
+            check_limits(CommandMode::Create, &username, "rust", "1"),
+            Err(DatabaseError::FieldTooLong {
+                field: "username",
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_limits(CommandMode::Create, "fork", "rust", "1\u{7}"),
+            Err(DatabaseError::ReservedCharacter {
+                field: "discord id",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn removals_are_never_refused() {
+        let limits = Config::get().limits;
+        let languages = vec!["rust"; limits.max_languages + 1].join("|");
+        assert!(check_limits(CommandMode::RemoveLanguage, "fork", &languages, "").is_ok());
+        let username = "a".repeat(limits.max_username_len + 1);
+        assert!(check_limits(CommandMode::Destroy, &username, "", "").is_ok());
+    }
+}
@@ -0,0 +1,597 @@
+use crate::api::Caller;
+use crate::config::Config;
+use crate::server::{constant_time_eq, FILEPATH};
+use crate::user::rewrite;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use protocol::SessionResponse;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// marks a signed access token.
+pub const ACCESS_PREFIX: &str = "cca_";
+/// marks a refresh token: `ccr_<session id>.<secret>`.
+pub const REFRESH_PREFIX: &str = "ccr_";
+/// how many spent refresh tokens a session remembers. one older than that
+/// coming back is only refused, not taken as a sign of theft.
+const MAX_SPENT: usize = 32;
+
+/// why a refresh was refused.
+#[derive(Debug)]
+pub enum RefreshError {
+    /// unknown, expired, revoked or simply wrong.
+    Invalid,
+    /// a refresh token that was already spent. someone holds a copy, so the
+    /// session is revoked.
+    Reused,
+    IoError(io::Error),
+}
+
+impl From<io::Error> for RefreshError {
+    fn from(error: io::Error) -> Self {
+        RefreshError::IoError(error)
+    }
+}
+
+/// what an access token says. it is checked by its signature and expiry
+/// alone, plus a look at the in-memory sessions, so requests never read the
+/// key store.
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// `Caller::name` of who logged in.
+    sub: String,
+    sid: String,
+    exp: u64,
+}
+
+/// one login. kept until its refresh token expires, revoked or not, which
+/// is always after the last access token of the session has expired.
+#[derive(Debug)]
+struct Session {
+    subject: String,
+    /// hash of the one refresh token that may be used next.
+    refresh_hash: String,
+    /// hashes of the refresh tokens already traded in, oldest first. only
+    /// one of these coming back means a copy was taken.
+    spent: Vec<String>,
+    /// for admin sessions, [`key_fingerprint`] of the api key they started
+    /// with. rotating the key ends them. empty for members.
+    key: String,
+    expires_at: u64,
+    revoked: bool,
+}
+
+impl Session {
+    fn from_csv_line(line: &str) -> Option<(String, Session)> {
+        let fields: Vec<&str> = line.split(',').collect();
+        let [sid, subject, refresh_hash, expires_at, revoked, key, spent] = fields[..] else {
+            return None;
+        };
+        Some((
+            sid.to_string(),
+            Session {
+                subject: subject.to_string(),
+                refresh_hash: refresh_hash.to_string(),
+                spent: spent
+                    .split('|')
+                    .filter(|hash| !hash.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                key: key.to_string(),
+                expires_at: expires_at.parse().ok()?,
+                revoked: revoked == "1",
+            },
+        ))
+    }
+
+    fn to_csv_line(&self, sid: &str) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            sid,
+            self.subject,
+            self.refresh_hash,
+            self.expires_at,
+            if self.revoked { "1" } else { "0" },
+            self.key,
+            self.spent.join("|")
+        )
+    }
+
+    /// whether the session still vouches for its subject. `key` is the
+    /// fingerprint of the api key loaded now.
+    fn is_live(&self, key: &str, now: u64) -> bool {
+        let key_matches = self.subject != Caller::Admin.name()
+            || constant_time_eq(self.key.as_bytes(), key.as_bytes());
+        !self.revoked && self.expires_at > now && key_matches
+    }
+}
+
+/// every session by id, mirrored to a file so they survive a restart.
+struct Store {
+    path: String,
+    sessions: HashMap<String, Session>,
+}
+
+impl Store {
+    fn open(path: String) -> io::Result<Self> {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Store {
+                    path,
+                    sessions: HashMap::new(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let mut sessions = HashMap::new();
+        for line in BufReader::new(file).lines() {
+            if let Some((sid, session)) = Session::from_csv_line(&line?) {
+                sessions.insert(sid, session);
+            }
+        }
+        Ok(Store { path, sessions })
+    }
+
+    /// drops expired sessions and writes the rest out.
+    fn save(&mut self, now: u64) -> io::Result<()> {
+        self.sessions.retain(|_, session| session.expires_at > now);
+        let lines: Vec<String> = self
+            .sessions
+            .iter()
+            .map(|(sid, session)| session.to_csv_line(sid))
+            .collect();
+        rewrite(&self.path, &lines)
+    }
+
+    /// starts a session for `subject`, returning its id and first refresh
+    /// token.
+    fn login(
+        &mut self,
+        subject: String,
+        key: String,
+        ttl: u64,
+        now: u64,
+    ) -> io::Result<(String, String)> {
+        let sid = uuid::Uuid::new_v4().simple().to_string();
+        let (refresh_token, refresh_hash) = refresh_token(&sid);
+        self.sessions.insert(
+            sid.clone(),
+            Session {
+                subject,
+                refresh_hash,
+                spent: Vec::new(),
+                key,
+                expires_at: now + ttl,
+                revoked: false,
+            },
+        );
+        self.save(now)?;
+        Ok((sid, refresh_token))
+    }
+
+    /// trades a refresh token for the next one, returning the session's
+    /// subject, id and new refresh token.
+    fn refresh(
+        &mut self,
+        token: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<(String, String, String), RefreshError> {
+        let sid = session_id(token).ok_or(RefreshError::Invalid)?;
+        let session = self.sessions.get_mut(sid).ok_or(RefreshError::Invalid)?;
+        if !session.is_live(key, now) {
+            return Err(RefreshError::Invalid);
+        }
+
+        let presented = hash(token);
+        if !constant_time_eq(presented.as_bytes(), session.refresh_hash.as_bytes()) {
+            // the session id is in every access token, so a wrong secret on
+            // its own says nothing; only a spent one proves a copy exists
+            let spent = session
+                .spent
+                .iter()
+                .any(|hash| constant_time_eq(presented.as_bytes(), hash.as_bytes()));
+            if !spent {
+                return Err(RefreshError::Invalid);
+            }
+            session.revoked = true;
+            tracing::warn!(subject = %session.subject, "spent refresh token reused, revoking the session");
+            self.save(now)?;
+            return Err(RefreshError::Reused);
+        }
+
+        let (refresh_token, refresh_hash) = refresh_token(sid);
+        let spent = std::mem::replace(&mut session.refresh_hash, refresh_hash);
+        session.spent.push(spent);
+        if session.spent.len() > MAX_SPENT {
+            session.spent.remove(0);
+        }
+        let subject = session.subject.clone();
+        let sid = sid.to_string();
+        self.save(now)?;
+        Ok((subject, sid, refresh_token))
+    }
+
+    fn logout(&mut self, token: &str, now: u64) -> io::Result<bool> {
+        let Some(sid) = session_id(token) else {
+            return Ok(false);
+        };
+        match self.sessions.get_mut(sid) {
+            Some(session)
+                if !session.revoked
+                    && constant_time_eq(
+                        hash(token).as_bytes(),
+                        session.refresh_hash.as_bytes(),
+                    ) =>
+            {
+                session.revoked = true;
+                self.save(now)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// revokes the live sessions `matching` picks out, returning how many.
+    fn revoke(&mut self, matching: impl Fn(&Session) -> bool, now: u64) -> io::Result<usize> {
+        let mut revoked = 0;
+        for session in self.sessions.values_mut() {
+            if !session.revoked && matching(session) {
+                session.revoked = true;
+                revoked += 1;
+            }
+        }
+        if revoked > 0 {
+            self.save(now)?;
+        }
+        Ok(revoked)
+    }
+
+    fn is_live(&self, sid: &str, key: &str, now: u64) -> bool {
+        self.sessions
+            .get(sid)
+            .is_some_and(|session| session.is_live(key, now))
+    }
+}
+
+/// the sessions of the store at [`FILEPATH`], loaded on first use.
+static STORE: LazyLock<Mutex<Store>> = LazyLock::new(|| {
+    let path = sessions_path(FILEPATH);
+    let store = Store::open(path.clone()).unwrap_or_else(|e| {
+        tracing::error!("could not load sessions, starting with none: {}", e);
+        Store {
+            path,
+            sessions: HashMap::new(),
+        }
+    });
+    Mutex::new(store)
+});
+
+/// used when `SESSION_SECRET` is unset, so access tokens last until a restart.
+static GENERATED_SECRET: LazyLock<String> = LazyLock::new(random);
+
+fn store() -> MutexGuard<'static, Store> {
+    STORE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn sessions_path(file_path: &str) -> String {
+    match file_path.strip_suffix(".csv") {
+        Some(stem) => format!("{}.sessions.csv", stem),
+        None => format!("{}.sessions", file_path),
+    }
+}
+
+/// runs `restore`, which puts a backup over the files, then rereads the
+/// sessions from disk. they stay locked from start to end, so a login or
+/// refresh in between can't write the old sessions back over the restored
+/// file.
+pub fn restore_with<T, E: From<io::Error>>(restore: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let mut store = store();
+    let restored = restore()?;
+    *store = Store::open(store.path.clone())?;
+    Ok(restored)
+}
+
+/// starts a session for someone who just proved who they are.
+pub fn login(caller: &Caller) -> io::Result<SessionResponse> {
+    let key = match caller {
+        Caller::Admin => key_fingerprint(&Config::get().api_key),
+        Caller::Member(_) => String::new(),
+    };
+    let ttl = Config::get().refresh_token_ttl.as_secs();
+    let (sid, refresh_token) = store().login(caller.name(), key, ttl, now())?;
+    Ok(respond(&caller.name(), &sid, refresh_token))
+}
+
+/// trades a refresh token for a new access token and a new refresh token.
+/// the old refresh token stops working, and ends the session if it is ever
+/// used again.
+pub fn refresh(token: &str) -> Result<SessionResponse, RefreshError> {
+    let key = key_fingerprint(&Config::get().api_key);
+    let (subject, sid, refresh_token) = store().refresh(token, &key, now())?;
+    Ok(respond(&subject, &sid, refresh_token))
+}
+
+/// ends the session a refresh token belongs to. false when it didn't name
+/// a live session.
+pub fn logout(token: &str) -> io::Result<bool> {
+    store().logout(token, now())
+}
+
+/// ends every session of `caller`, e.g. when its personal token is revoked.
+pub fn revoke_all(caller: &Caller) -> io::Result<usize> {
+    let subject = caller.name();
+    store().revoke(|session| session.subject == subject, now())
+}
+
+/// ends every session there is, admin and member alike.
+pub fn revoke_everyone() -> io::Result<usize> {
+    store().revoke(|_| true, now())
+}
+
+/// who an access token was issued to, if it is ours, unexpired and its
+/// session is still live.
+pub fn verify(token: &str) -> Option<Caller> {
+    let claims = decode(token, &secret(), now())?;
+    let key = key_fingerprint(&Config::get().api_key);
+    if !store().is_live(&claims.sid, &key, now()) {
+        return None;
+    }
+    Caller::from_name(&claims.sub)
+}
+
+fn respond(subject: &str, sid: &str, refresh_token: String) -> SessionResponse {
+    let config = Config::get();
+    let claims = Claims {
+        sub: subject.to_string(),
+        sid: sid.to_string(),
+        exp: now() + config.access_token_ttl.as_secs(),
+    };
+    SessionResponse {
+        access_token: encode(&claims, &secret()),
+        token_type: "Bearer".to_string(),
+        expires_in: config.access_token_ttl.as_secs(),
+        refresh_token,
+        refresh_expires_in: config.refresh_token_ttl.as_secs(),
+    }
+}
+
+fn encode(claims: &Claims, secret: &str) -> String {
+    let payload =
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims always serialize"));
+    let signature =
+        URL_SAFE_NO_PAD.encode(mac(secret).chain_update(&payload).finalize().into_bytes());
+    format!("{}{}.{}", ACCESS_PREFIX, payload, signature)
+}
+
+/// the claims of an access token signed with `secret` that hasn't expired.
+fn decode(token: &str, secret: &str, now: u64) -> Option<Claims> {
+    let (payload, signature) = token.strip_prefix(ACCESS_PREFIX)?.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    mac(secret)
+        .chain_update(payload)
+        .verify_slice(&signature)
+        .ok()?;
+
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    (claims.exp > now).then_some(claims)
+}
+
+fn secret() -> String {
+    Config::get()
+        .session_secret
+        .clone()
+        .unwrap_or_else(|| GENERATED_SECRET.clone())
+}
+
+fn mac(secret: &str) -> Hmac<Sha256> {
+    Hmac::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length")
+}
+
+/// a short hash of the api key, stored with admin sessions so they can tell
+/// when the key has changed without keeping the key itself.
+fn key_fingerprint(api_key: &str) -> String {
+    hash(api_key)[..16].to_string()
+}
+
+fn session_id(refresh_token: &str) -> Option<&str> {
+    refresh_token
+        .strip_prefix(REFRESH_PREFIX)
+        .and_then(|rest| rest.split_once('.'))
+        .map(|(sid, _)| sid)
+}
+
+/// a new refresh token for session `sid` and the hash kept of it.
+fn refresh_token(sid: &str) -> (String, String) {
+    let token = format!("{}{}.{}", REFRESH_PREFIX, sid, random());
+    let hash = hash(&token);
+    (token, hash)
+}
+
+fn random() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const SECRET: &str = "a test secret of at least 32 bytes";
+    const KEY: &str = "fingerprint";
+    const NOW: u64 = 1_000_000;
+
+    fn store(dir: &TempDir) -> Store {
+        Store {
+            path: dir.file("users.sessions.csv"),
+            sessions: HashMap::new(),
+        }
+    }
+
+    fn claims(exp: u64) -> Claims {
+        Claims {
+            sub: "user:fork".to_string(),
+            sid: "abc".to_string(),
+            exp,
+        }
+    }
+
+    #[test]
+    fn access_token_verifies_with_its_secret() {
+        let token = encode(&claims(NOW + 60), SECRET);
+        let decoded = decode(&token, SECRET, NOW).unwrap();
+        assert_eq!(decoded.sub, "user:fork");
+        assert_eq!(decoded.sid, "abc");
+    }
+
+    #[test]
+    fn access_token_is_refused_with_another_secret() {
+        let token = encode(&claims(NOW + 60), SECRET);
+        assert!(decode(&token, "some other secret of 32 bytes or more", NOW).is_none());
+    }
+
+    #[test]
+    fn tampered_access_token_is_refused() {
+        let token = encode(&claims(NOW + 60), SECRET);
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&Claims {
+                sub: "API_KEY".to_string(),
+                ..claims(NOW + 60)
+            })
+            .unwrap(),
+        );
+        let forged = format!("{}{}.{}", ACCESS_PREFIX, forged, signature);
+        assert!(decode(&forged, SECRET, NOW).is_none());
+    }
+
+    #[test]
+    fn expired_access_token_is_refused() {
+        let token = encode(&claims(NOW), SECRET);
+        assert!(decode(&token, SECRET, NOW).is_none());
+    }
+
+    #[test]
+    fn refresh_rotates_the_token() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (sid, first) = store
+            .login("user:fork".to_string(), String::new(), 60, NOW)
+            .unwrap();
+
+        let (subject, refreshed_sid, second) = store.refresh(&first, KEY, NOW).unwrap();
+        assert_eq!(subject, "user:fork");
+        assert_eq!(refreshed_sid, sid);
+        assert_ne!(second, first);
+        assert!(store.refresh(&second, KEY, NOW).is_ok());
+    }
+
+    #[test]
+    fn reusing_a_spent_refresh_token_revokes_the_session() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (sid, first) = store
+            .login("user:fork".to_string(), String::new(), 60, NOW)
+            .unwrap();
+        let (_, _, second) = store.refresh(&first, KEY, NOW).unwrap();
+
+        assert!(matches!(
+            store.refresh(&first, KEY, NOW),
+            Err(RefreshError::Reused)
+        ));
+        assert!(!store.is_live(&sid, KEY, NOW));
+        assert!(matches!(
+            store.refresh(&second, KEY, NOW),
+            Err(RefreshError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn a_wrong_refresh_secret_leaves_the_session_alone() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (sid, token) = store
+            .login("user:fork".to_string(), String::new(), 60, NOW)
+            .unwrap();
+
+        let guess = format!("{}{}.garbage", REFRESH_PREFIX, sid);
+        assert!(matches!(
+            store.refresh(&guess, KEY, NOW),
+            Err(RefreshError::Invalid)
+        ));
+        assert!(store.is_live(&sid, KEY, NOW));
+        assert!(store.refresh(&token, KEY, NOW).is_ok());
+    }
+
+    #[test]
+    fn expired_session_does_not_refresh() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (_, token) = store
+            .login("user:fork".to_string(), String::new(), 60, NOW)
+            .unwrap();
+        assert!(matches!(
+            store.refresh(&token, KEY, NOW + 60),
+            Err(RefreshError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn admin_sessions_end_when_the_key_changes() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (sid, token) = store
+            .login(Caller::Admin.name(), KEY.to_string(), 60, NOW)
+            .unwrap();
+
+        assert!(store.is_live(&sid, KEY, NOW));
+        assert!(!store.is_live(&sid, "rotated", NOW));
+        assert!(matches!(
+            store.refresh(&token, "rotated", NOW),
+            Err(RefreshError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn sessions_survive_a_reload() {
+        let dir = TempDir::new();
+        let mut store = store(&dir);
+        let (sid, first) = store
+            .login("user:fork".to_string(), String::new(), 60, NOW)
+            .unwrap();
+        let (_, _, second) = store.refresh(&first, KEY, NOW).unwrap();
+
+        let mut reloaded = Store::open(store.path.clone()).unwrap();
+        assert!(reloaded.is_live(&sid, KEY, NOW));
+        assert!(matches!(
+            reloaded.refresh(&first, KEY, NOW),
+            Err(RefreshError::Reused)
+        ));
+        assert!(matches!(
+            reloaded.refresh(&second, KEY, NOW),
+            Err(RefreshError::Invalid)
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// a directory of its own for one test, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path =
+            std::env::temp_dir().join(format!("ccweb-test-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&path).expect("temp dir is writable");
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// `name` inside the directory, as the `&str` paths the store takes.
+    pub fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
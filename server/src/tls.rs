@@ -0,0 +1,54 @@
+use crate::config::TlsPaths;
+use axum_server::tls_rustls::RustlsConfig;
+use std::{fs, io, time::Duration, time::SystemTime};
+
+/// reads the certificate chain and key named by `paths`.
+pub async fn load(paths: &TlsPaths) -> io::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&paths.cert, &paths.key)
+        .await
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "could not load tls certificate {} and key {}: {}",
+                    paths.cert.display(),
+                    paths.key.display(),
+                    e
+                ),
+            )
+        })
+}
+
+/// checks the files every `interval` and swaps in the new certificate when
+/// either has changed, so a renewal doesn't need a restart. connections
+/// already open keep the certificate they started with. a renewal that
+/// doesn't load is logged and the current certificate stays in use.
+pub async fn reload_on_change(rustls: RustlsConfig, paths: TlsPaths, interval: Duration) {
+    let mut seen = modified(&paths);
+    let mut ticks = tokio::time::interval(interval);
+    ticks.tick().await;
+
+    loop {
+        ticks.tick().await;
+        let current = modified(&paths);
+        if current == seen {
+            continue;
+        }
+        // remembered even when loading fails: renewals often write the cert
+        // and key one after the other, and the second write retries
+        seen = current;
+
+        match rustls.reload_from_pem_file(&paths.cert, &paths.key).await {
+            Ok(()) => tracing::info!("reloaded tls certificate {}", paths.cert.display()),
+            Err(e) => tracing::warn!(
+                "keeping the current tls certificate, could not load the new one: {}",
+                e
+            ),
+        }
+    }
+}
+
+fn modified(paths: &TlsPaths) -> Option<(SystemTime, SystemTime)> {
+    let mtime = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((mtime(&paths.cert)?, mtime(&paths.key)?))
+}
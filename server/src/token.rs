@@ -0,0 +1,166 @@
+use crate::server::constant_time_eq;
+use crate::user::rewrite;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// marks a personal token, so it is never mistaken for the admin key.
+pub const PREFIX: &str = "ccu_";
+
+/// personal tokens live next to the store in `users.tokens.csv`, one
+/// `username,sha256,issued_at` line per user. only the hash is kept; the
+/// token itself is shown once, when it is issued. tokens are random, so a
+/// fast hash is enough where a password would want bcrypt.
+///
+/// guarded by the store lock like the store itself.
+pub fn tokens_path(file_path: &str) -> String {
+    match file_path.strip_suffix(".csv") {
+        Some(stem) => format!("{}.tokens.csv", stem),
+        None => format!("{}.tokens", file_path),
+    }
+}
+
+struct Entry {
+    username: String,
+    hash: String,
+    issued_at: u64,
+}
+
+impl Entry {
+    fn from_csv_line(line: &str) -> Option<Self> {
+        let mut fields = line.split(',');
+        let username = fields.next()?.to_string();
+        let hash = fields.next()?.to_string();
+        let issued_at = fields.next()?.parse().ok()?;
+        Some(Entry {
+            username,
+            hash,
+            issued_at,
+        })
+    }
+
+    fn to_csv_line(&self) -> String {
+        format!("{},{},{}", self.username, self.hash, self.issued_at)
+    }
+}
+
+fn load(file_path: &str) -> io::Result<Vec<Entry>> {
+    let file = match File::open(tokens_path(file_path)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Some(entry) = Entry::from_csv_line(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn save(file_path: &str, entries: &[Entry]) -> io::Result<()> {
+    let lines: Vec<String> = entries.iter().map(Entry::to_csv_line).collect();
+    rewrite(&tokens_path(file_path), &lines)
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// issues a new token for `username`, replacing any it had. the caller
+/// checks the user exists.
+pub fn issue(file_path: &str, username: &str) -> io::Result<String> {
+    let token = format!(
+        "{}{}{}",
+        PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let mut entries = load(file_path)?;
+    entries.retain(|entry| entry.username != username);
+    entries.push(Entry {
+        username: username.to_string(),
+        hash: hash(&token),
+        issued_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+    });
+    save(file_path, &entries)?;
+    Ok(token)
+}
+
+/// drops the tokens of `usernames`. true when there was one to drop.
+pub fn revoke(file_path: &str, usernames: &[String]) -> io::Result<bool> {
+    let mut entries = load(file_path)?;
+    let before = entries.len();
+    entries.retain(|entry| !usernames.contains(&entry.username));
+    if entries.len() == before {
+        return Ok(false);
+    }
+    save(file_path, &entries)?;
+    Ok(true)
+}
+
+/// the user a token belongs to, if it is one we issued.
+pub fn owner(file_path: &str, token: &str) -> io::Result<Option<String>> {
+    let presented = hash(token);
+    // every entry is compared so the time taken says nothing about which
+    let mut found = None;
+    for entry in load(file_path)? {
+        if constant_time_eq(presented.as_bytes(), entry.hash.as_bytes()) {
+            found = Some(entry.username);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::fs;
+
+    #[test]
+    fn issued_token_names_its_owner() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+
+        let token = issue(&file_path, "fork").unwrap();
+        assert!(token.starts_with(PREFIX));
+        assert_eq!(owner(&file_path, &token).unwrap().as_deref(), Some("fork"));
+        assert_eq!(owner(&file_path, "ccu_guessed").unwrap(), None);
+        // only the hash is kept
+        let stored = fs::read_to_string(tokens_path(&file_path)).unwrap();
+        assert!(!stored.contains(&token));
+    }
+
+    #[test]
+    fn reissuing_replaces_the_old_token() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+
+        let old = issue(&file_path, "fork").unwrap();
+        let alice = issue(&file_path, "alice").unwrap();
+        let new = issue(&file_path, "fork").unwrap();
+        assert_eq!(owner(&file_path, &old).unwrap(), None);
+        assert_eq!(owner(&file_path, &new).unwrap().as_deref(), Some("fork"));
+        assert_eq!(owner(&file_path, &alice).unwrap().as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn revoke_drops_only_the_named_tokens() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+
+        let fork = issue(&file_path, "fork").unwrap();
+        let alice = issue(&file_path, "alice").unwrap();
+        assert!(revoke(&file_path, &["fork".to_string()]).unwrap());
+        assert!(!revoke(&file_path, &["fork".to_string()]).unwrap());
+        assert_eq!(owner(&file_path, &fork).unwrap(), None);
+        assert_eq!(owner(&file_path, &alice).unwrap().as_deref(), Some("alice"));
+    }
+}
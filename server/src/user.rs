@@ -1,69 +1,40 @@
+use crate::api::Caller;
+use crate::config::Config;
+use crate::metrics;
+use crate::session;
+use crate::token;
+pub use protocol::{Language, UserVersion};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::str::FromStr;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// guards the store files (`users.csv` and its history). callers hold a read
+/// guard while they only read and a write guard across anything that writes,
+/// so a rewrite is never observed half done by another request or a backup.
+static STORE_LOCK: RwLock<()> = RwLock::new(());
+
+pub fn read_lock() -> RwLockReadGuard<'static, ()> {
+    STORE_LOCK.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn write_lock() -> RwLockWriteGuard<'static, ()> {
+    STORE_LOCK.write().unwrap_or_else(PoisonError::into_inner)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub username: String,
     pub languages: Vec<Language>,
     pub discord_id: String,
-}
-
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Language {
-    C,
-    CPP,
-    CSharp,
-    Java,
-    JavaScript,
-    TypeScript,
-    Python,
-    Ruby,
-    Rust,
-    Go,
-    Swift,
-    Kotlin,
-    Lua,
-    PHP,
-    Perl,
-    ObjectiveC,
-    Scala,
-    Haskell,
-    Shell,
-    R,
-    Julia,
-    Dart,
-    VB,
-    FSharp,
-    Lisp,
-    Prolog,
-    Assembly,
-    SQL,
-    HTML,
-    CSS,
-    Verilog,
-    Matlab,
-    Cobol,
-    Fortran,
-    Ada,
-    Delphi,
-    Smalltalk,
-    Erlang,
-    Tcl,
-    Scheme,
-    Apex,
-    ApexTrigger,
-    CoffeeScript,
-    Elm,
-    PureScript,
-    Crystal,
-    Elixir,
-    Raku,
-    Hack,
-    VHDL,
-    BadLanguage,
+    /// unix timestamp of a soft delete. deleted users are hidden from lookups
+    /// until they are restored or purged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -71,72 +42,77 @@ pub enum DatabaseError {
     MissingUsername,
     UserNotFound,
     UserAlreadyExists,
+    UserNotDeleted,
+    VersionNotFound,
+    /// a field is longer than the configured limit.
+    FieldTooLong {
+        field: &'static str,
+        max: usize,
+    },
+    /// a field holds a character that would break the csv framing.
+    ReservedCharacter {
+        field: &'static str,
+        character: char,
+    },
+    TooManyLanguages {
+        max: usize,
+    },
+    /// the store already holds the configured maximum of users.
+    TooManyUsers {
+        max: usize,
+    },
+    RegistrationNotFound,
+    /// the approval queue already holds the configured maximum.
+    QueueFull {
+        max: usize,
+    },
     IoError(io::Error),
 }
 
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::MissingUsername => write!(f, "Missing username"),
+            DatabaseError::UserNotFound => write!(f, "User not found"),
+            DatabaseError::UserAlreadyExists => write!(f, "User already exists"),
+            DatabaseError::UserNotDeleted => write!(f, "User is not deleted"),
+            DatabaseError::VersionNotFound => write!(f, "Version not found"),
+            DatabaseError::FieldTooLong { field, max } => {
+                write!(f, "{} is longer than {} characters", field, max)
+            }
+            DatabaseError::ReservedCharacter { field, character } => {
+                write!(f, "{} contains reserved character {:?}", field, character)
+            }
+            DatabaseError::TooManyLanguages { max } => {
+                write!(f, "A user can know at most {} languages", max)
+            }
+            DatabaseError::TooManyUsers { max } => {
+                write!(f, "The store is full at {} users", max)
+            }
+            DatabaseError::RegistrationNotFound => write!(f, "Registration not found"),
+            DatabaseError::QueueFull { max } => {
+                write!(f, "{} registrations are already waiting for approval", max)
+            }
+            DatabaseError::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl Error for DatabaseError {}
+
 impl From<io::Error> for DatabaseError {
     fn from(error: io::Error) -> Self {
         DatabaseError::IoError(error)
     }
 }
 
-impl FromStr for Language {
-    type Err = ();
-
-    fn from_str(input: &str) -> Result<Language, Self::Err> {
-        let lowercased_input = input.trim().to_lowercase();
-        match lowercased_input.as_str() {
-            "c" => Ok(Language::C),
-            "cpp" => Ok(Language::CPP),
-            "csharp" | "c#" => Ok(Language::CSharp),
-            "java" => Ok(Language::Java),
-            "javascript" | "js" => Ok(Language::JavaScript),
-            "typescript" | "ts" => Ok(Language::TypeScript),
-            "python" | "py" => Ok(Language::Python),
-            "ruby" => Ok(Language::Ruby),
-            "rust" => Ok(Language::Rust),
-            "go" | "golang" => Ok(Language::Go),
-            "swift" => Ok(Language::Swift),
-            "kotlin" => Ok(Language::Kotlin),
-            "lua" => Ok(Language::Lua),
-            "php" => Ok(Language::PHP),
-            "perl" => Ok(Language::Perl),
-            "objc" | "objective-c" => Ok(Language::ObjectiveC),
-            "scala" => Ok(Language::Scala),
-            "haskell" => Ok(Language::Haskell),
-            "shell" | "bash" => Ok(Language::Shell),
-            "r" => Ok(Language::R),
-            "julia" => Ok(Language::Julia),
-            "dart" => Ok(Language::Dart),
-            "vb" | "visualbasic" => Ok(Language::VB),
-            "fsharp" => Ok(Language::FSharp),
-            "lisp" => Ok(Language::Lisp),
-            "prolog" => Ok(Language::Prolog),
-            "assembly" => Ok(Language::Assembly),
-            "sql" => Ok(Language::SQL),
-            "html" => Ok(Language::HTML),
-            "css" => Ok(Language::CSS),
-            "verilog" => Ok(Language::Verilog),
-            "vhdl" => Ok(Language::VHDL),
-            "matlab" => Ok(Language::Matlab),
-            "cobol" => Ok(Language::Cobol),
-            "fortran" => Ok(Language::Fortran),
-            "ada" => Ok(Language::Ada),
-            "delphi" => Ok(Language::Delphi),
-            "smalltalk" => Ok(Language::Smalltalk),
-            "erlang" => Ok(Language::Erlang),
-            "tcl" => Ok(Language::Tcl),
-            "scheme" => Ok(Language::Scheme),
-            "apex" => Ok(Language::Apex),
-            "apextrigger" => Ok(Language::ApexTrigger),
-            "coffeescript" => Ok(Language::CoffeeScript),
-            "elm" => Ok(Language::Elm),
-            "purescript" => Ok(Language::PureScript),
-            "crystal" => Ok(Language::Crystal),
-            "elixir" => Ok(Language::Elixir),
-            "raku" => Ok(Language::Raku),
-            "hack" => Ok(Language::Hack),
-            _ => Err(()),
+impl From<User> for protocol::User {
+    fn from(user: User) -> Self {
+        protocol::User {
+            username: user.username,
+            languages: user.languages,
+            discord_id: user.discord_id,
+            deleted_at: user.deleted_at,
         }
     }
 }
@@ -148,16 +124,18 @@ impl User {
         discord_id: Option<String>,
     ) -> Result<Self, DatabaseError> {
         let username = match username {
-            Some(u) => u,
-            None => return Err(DatabaseError::MissingUsername),
+            Some(u) if !u.trim().is_empty() => u,
+            _ => return Err(DatabaseError::MissingUsername),
         };
         let languages = languages.unwrap_or_default();
         let discord_id = discord_id.unwrap_or_default();
+        Self::validate(&username, &languages, &discord_id)?;
 
         Ok(User {
             username,
             languages,
             discord_id,
+            deleted_at: None,
         })
     }
 
@@ -166,11 +144,17 @@ impl User {
         new_languages: Vec<Language>,
         file_path: &str,
     ) -> Result<(), DatabaseError> {
+        let mut languages = self.languages.clone();
         for language in new_languages {
-            if !self.languages.contains(&language) {
-                self.languages.push(language);
+            if !languages.contains(&language) {
+                languages.push(language);
             }
         }
+        let max = Config::get().limits.max_languages;
+        if languages.len() > max {
+            return Err(DatabaseError::TooManyLanguages { max });
+        }
+        self.languages = languages;
 
         self.update_user(file_path)?;
 
@@ -189,127 +173,373 @@ impl User {
         Ok(())
     }
 
+    /// checks a new user against the configured limits and the characters
+    /// the csv can't hold.
+    pub fn validate(
+        username: &str,
+        languages: &[Language],
+        discord_id: &str,
+    ) -> Result<(), DatabaseError> {
+        let limits = Config::get().limits;
+        for (field, value, max) in [
+            ("username", username, limits.max_username_len),
+            ("discord id", discord_id, limits.max_discord_id_len),
+        ] {
+            if value.chars().count() > max {
+                return Err(DatabaseError::FieldTooLong { field, max });
+            }
+            if let Some(character) = value.chars().find(|c| is_reserved(*c)) {
+                return Err(DatabaseError::ReservedCharacter { field, character });
+            }
+        }
+        if languages.len() > limits.max_languages {
+            return Err(DatabaseError::TooManyLanguages {
+                max: limits.max_languages,
+            });
+        }
+        Ok(())
+    }
+
+    /// finds a live user. soft deleted users are treated as not found.
     pub fn lookup_user(file_path: &str, username: &str) -> Result<User, DatabaseError> {
-        let file = File::open(file_path)?;
+        match Self::lookup_any(file_path, username)? {
+            Some(user) if user.deleted_at.is_none() => Ok(user),
+            _ => Err(DatabaseError::UserNotFound),
+        }
+    }
+
+    pub fn save_to_csv(&self, file_path: &str) -> Result<(), DatabaseError> {
+        metrics::time_store("save", || {
+            // a soft deleted user still holds its name until it is purged
+            if Self::lookup_any(file_path, &self.username)?.is_some() {
+                return Err(DatabaseError::UserAlreadyExists);
+            }
+            let max = Config::get().limits.max_users;
+            if Self::count(file_path)? >= max {
+                return Err(DatabaseError::TooManyUsers { max });
+            }
+
+            let mut file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(file_path)?;
+            writeln!(file, "{}", self.to_csv_line())?;
+            Ok(())
+        })
+    }
+
+    /// soft deletes a user: the row stays in the file with a `deleted_at`
+    /// timestamp so it can be restored until the retention window runs out.
+    /// its personal token and sessions end now; a restored user needs a new
+    /// token.
+    pub fn remove_user(file_path: &str, username: &str) -> Result<(), DatabaseError> {
+        let mut user = Self::lookup_user(file_path, username)?;
+        user.deleted_at = Some(now());
+        user.update_user(file_path)?;
+        token::revoke(file_path, &[user.username.clone()])?;
+        session::revoke_all(&Caller::Member(user.username))?;
+        Ok(())
+    }
+
+    /// brings back a soft deleted user.
+    pub fn restore_user(file_path: &str, username: &str) -> Result<User, DatabaseError> {
+        let mut user = match Self::lookup_any(file_path, username)? {
+            Some(user) => user,
+            None => return Err(DatabaseError::UserNotFound),
+        };
+        if user.deleted_at.is_none() {
+            return Err(DatabaseError::UserNotDeleted);
+        }
+        user.deleted_at = None;
+        user.update_user(file_path)?;
+        Ok(user)
+    }
+
+    /// permanently drops users that were soft deleted more than `retention`
+    /// ago. returns the usernames that were purged.
+    pub fn purge_deleted(file_path: &str, retention: Duration) -> io::Result<Vec<String>> {
+        let Some(file) = open_store(file_path)? else {
+            return Ok(Vec::new());
+        };
         let reader = BufReader::new(file);
+        let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+        let cutoff = now().saturating_sub(retention.as_secs());
 
-        for line in reader.lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() >= 3 && parts[0] == username {
-                let username = parts[0].to_string();
-                let languages = parts[1]
-                    .split('|')
-                    .filter_map(|s| Language::from_str(s.trim()).ok())
-                    .collect();
-                let discord_id = parts[2].to_string();
-                return Ok(User {
+        let mut purged = Vec::new();
+        let mut kept = Vec::new();
+        for line in lines {
+            match Self::from_csv_line(&line) {
+                Some(User {
                     username,
-                    languages,
-                    discord_id,
-                });
+                    deleted_at: Some(deleted_at),
+                    ..
+                }) if deleted_at <= cutoff => purged.push(username),
+                _ => kept.push(line),
             }
         }
-        Err(DatabaseError::UserNotFound)
+
+        if !purged.is_empty() {
+            metrics::time_store("rewrite", || rewrite(file_path, &kept))?;
+            Self::purge_history(file_path, &purged)?;
+            // the name can be taken again and must not come with the old
+            // holder's token or sessions
+            token::revoke(file_path, &purged)?;
+            for username in &purged {
+                session::revoke_all(&Caller::Member(username.clone()))?;
+            }
+        }
+        Ok(purged)
     }
 
-    pub fn save_to_csv(&self, file_path: &str) -> Result<(), DatabaseError> {
-        let file = File::open(file_path)?;
+    /// every version of a user, oldest first, ending with the live record.
+    pub fn history(file_path: &str, username: &str) -> Result<Vec<UserVersion>, DatabaseError> {
+        let mut versions = Self::archived_versions(file_path, username)?;
+        let current = match Self::lookup_any(file_path, username)? {
+            Some(user) => user,
+            None => return Err(DatabaseError::UserNotFound),
+        };
+        versions.push(UserVersion {
+            version: versions.len() as u32 + 1,
+            superseded_at: None,
+            user: current.into(),
+        });
+        Ok(versions)
+    }
+
+    /// the version of a user that was in effect at unix time `at`.
+    pub fn version_at(
+        file_path: &str,
+        username: &str,
+        at: u64,
+    ) -> Result<UserVersion, DatabaseError> {
+        Self::history(file_path, username)?
+            .into_iter()
+            .find(|v| {
+                v.superseded_at
+                    .is_none_or(|superseded_at| at < superseded_at)
+            })
+            .ok_or(DatabaseError::VersionNotFound)
+    }
+
+    /// rolls a live user's languages and discord id back to an earlier
+    /// version. the record being replaced is archived like any other edit.
+    pub fn revert_to(file_path: &str, username: &str, version: u32) -> Result<User, DatabaseError> {
+        let mut user = Self::lookup_user(file_path, username)?;
+        let target = Self::history(file_path, username)?
+            .into_iter()
+            .find(|v| v.version == version)
+            .ok_or(DatabaseError::VersionNotFound)?;
+
+        user.languages = target.user.languages;
+        user.discord_id = target.user.discord_id;
+        user.update_user(file_path)?;
+        Ok(user)
+    }
+
+    /// every live user, in file order.
+    pub fn all(file_path: &str) -> Result<Vec<User>, DatabaseError> {
+        let Some(file) = open_store(file_path)? else {
+            return Ok(Vec::new());
+        };
         let reader = BufReader::new(file);
 
+        let mut users = Vec::new();
         for line in reader.lines() {
             let line = line?;
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() >= 1 && parts[0] == self.username {
-                return Err(DatabaseError::UserAlreadyExists);
+            if let Some(user) = Self::from_csv_line(&line) {
+                if user.deleted_at.is_none() {
+                    users.push(user);
+                }
             }
         }
+        Ok(users)
+    }
+
+    /// rows in the store, soft deleted users included.
+    pub fn count(file_path: &str) -> Result<usize, DatabaseError> {
+        let Some(file) = open_store(file_path)? else {
+            return Ok(0);
+        };
+        let mut count = 0;
+        for line in BufReader::new(file).lines() {
+            if Self::from_csv_line(&line?).is_some() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// whether a username is taken, counting soft deleted users.
+    pub fn exists(file_path: &str, username: &str) -> Result<bool, DatabaseError> {
+        Ok(Self::lookup_any(file_path, username)?.is_some())
+    }
+
+    /// writes this record over the stored row with the same username.
+    pub fn update_user(&self, file_path: &str) -> Result<(), DatabaseError> {
+        metrics::time_store("rewrite", || {
+            let mut lines: Vec<String> = match open_store(file_path)? {
+                Some(file) => BufReader::new(file).lines().collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            };
+            let mut found = false;
+
+            for line in lines.iter_mut() {
+                let parts: Vec<&str> = line.split(',').collect();
+                if parts[0] == self.username {
+                    self.archive_version(file_path, line)?;
+                    *line = self.to_csv_line();
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(file_path)?;
+                writeln!(file, "{}", self.to_csv_line())?;
+            } else {
+                rewrite(file_path, &lines)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    // version,superseded_at,<users.csv row>
+    fn archive_version(&self, file_path: &str, previous_line: &str) -> Result<(), DatabaseError> {
+        let history_path = history_path(file_path);
+        let version = match File::open(&history_path) {
+            Ok(_) => Self::archived_versions(file_path, &self.username)?.len() as u32 + 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 1,
+            Err(e) => return Err(e.into()),
+        };
 
         let mut file = OpenOptions::new()
             .append(true)
             .create(true)
-            .open(file_path)?;
-        let languages: Vec<String> = self.languages.iter().map(|l| format!("{:?}", l)).collect();
-        let line = format!(
-            "{},{},{}",
-            self.username,
-            languages.join("|"),
-            self.discord_id,
-        );
-        file.write_all(line.as_bytes())?;
+            .open(history_path)?;
+        writeln!(file, "{},{},{}", version, now(), previous_line)?;
         Ok(())
     }
 
-    pub fn remove_user(file_path: &str, username: &str) -> io::Result<()> {
-        let file = File::open(file_path)?;
+    fn archived_versions(
+        file_path: &str,
+        username: &str,
+    ) -> Result<Vec<UserVersion>, DatabaseError> {
+        let file = match File::open(history_path(file_path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let reader = BufReader::new(file);
+
+        let mut versions = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mut parts = line.splitn(3, ',');
+            let (Some(version), Some(superseded_at), Some(row)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let Some(user) = Self::from_csv_line(row) else {
+                continue;
+            };
+            if user.username != username {
+                continue;
+            }
+            if let (Ok(version), Ok(superseded_at)) = (version.parse(), superseded_at.parse()) {
+                versions.push(UserVersion {
+                    version,
+                    superseded_at: Some(superseded_at),
+                    user: user.into(),
+                });
+            }
+        }
+        Ok(versions)
+    }
+
+    fn purge_history(file_path: &str, usernames: &[String]) -> io::Result<()> {
+        let history_path = history_path(file_path);
+        let file = match File::open(&history_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
         let reader = BufReader::new(file);
         let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
-        let filtered_lines: Vec<String> = lines
+
+        let kept: Vec<String> = lines
             .into_iter()
             .filter(|line| {
-                let parts: Vec<&str> = line.split(',').collect();
-                parts.len() < 3 || parts[0] != username
+                let username = line.split(',').nth(2).unwrap_or_default();
+                !usernames.iter().any(|u| u == username)
             })
             .collect();
-        let mut file = File::create(file_path)?;
-        for line in filtered_lines {
-            writeln!(file, "{}", line)?;
-        }
-        Ok(())
+        rewrite(&history_path, &kept)
     }
 
-    pub fn to_string(&self) -> String {
-        let languages_str: Vec<String> =
-            self.languages.iter().map(|l| format!("{:?}", l)).collect();
-        format!(
-            "User {{ username: {}, languages: [{}], discord_id: {} }}",
-            self.username,
-            languages_str.join(", "),
-            self.discord_id
-        )
-    }
-    fn update_user(&self, file_path: &str) -> Result<(), DatabaseError> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
+    /// finds a user whether or not it has been soft deleted.
+    fn lookup_any(file_path: &str, username: &str) -> Result<Option<User>, DatabaseError> {
+        metrics::time_store("lookup", || {
+            let Some(file) = open_store(file_path)? else {
+                return Ok(None);
+            };
+            let reader = BufReader::new(file);
 
-        let mut lines: Vec<String> = reader.lines().map(|line| line.unwrap()).collect();
-        let mut found = false;
-
-        for line in lines.iter_mut() {
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() >= 1 && parts[0] == self.username {
-                *line = format!(
-                    "{},{},{}\n",
-                    self.username,
-                    self.languages_str(),
-                    self.discord_id
-                );
-                found = true;
-                break;
+            for line in reader.lines() {
+                let line = line?;
+                if let Some(user) = Self::from_csv_line(&line) {
+                    if user.username == username {
+                        return Ok(Some(user));
+                    }
+                }
             }
+            Ok(None)
+        })
+    }
+
+    // username,LANG|LANG,discord_id[,deleted_at]
+    pub fn from_csv_line(line: &str) -> Option<User> {
+        let parts: Vec<&str> = line.split(',').collect();
+        if parts.len() < 3 {
+            return None;
         }
+        let username = parts[0].to_string();
+        let languages = parts[1]
+            .split('|')
+            .filter_map(|s| Language::from_str(s.trim()).ok())
+            .collect();
+        let discord_id = parts[2].to_string();
+        let deleted_at = parts.get(3).and_then(|s| s.trim().parse().ok());
+        Some(User {
+            username,
+            languages,
+            discord_id,
+            deleted_at,
+        })
+    }
 
-        if !found {
-            let mut file = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(file_path)?;
-            let line = format!(
-                "{},{},{}\n",
+    pub fn to_csv_line(&self) -> String {
+        match self.deleted_at {
+            Some(deleted_at) => format!(
+                "{},{},{},{}",
                 self.username,
                 self.languages_str(),
                 self.discord_id,
-            );
-            file.write_all(line.as_bytes())?;
-        } else {
-            let mut file = File::create(file_path)?;
-            for line in lines {
-                writeln!(file, "{}", line)?;
-            }
+                deleted_at
+            ),
+            None => format!(
+                "{},{},{}",
+                self.username,
+                self.languages_str(),
+                self.discord_id
+            ),
         }
-
-        Ok(())
     }
+
     fn languages_str(&self) -> String {
         self.languages
             .iter()
@@ -318,3 +548,337 @@ impl User {
             .join("|")
     }
 }
+
+/// characters that would break the users.csv framing.
+pub fn is_reserved(c: char) -> bool {
+    c == ',' || c == '|' || c.is_control()
+}
+
+/// replaces the file at `path` with `lines` by writing a temporary file next
+/// to it and renaming that over the original, so a crash or kill leaves
+/// either the old contents or the new, never half of each.
+pub fn rewrite(path: &str, lines: &[String]) -> io::Result<()> {
+    replace(path, |file| {
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    })
+}
+
+/// [`rewrite`] for contents that are already bytes, e.g. a snapshot or a
+/// backup being put back.
+pub fn rewrite_bytes(path: &str, contents: &[u8]) -> io::Result<()> {
+    replace(path, |file| file.write_all(contents))
+}
+
+fn replace(
+    path: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let mut file = BufWriter::new(File::create(&tmp_path)?);
+    write(&mut file)?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// creates the store, its history and the token store if they don't exist
+/// yet, so a missing one later means it was removed under the server.
+pub fn create_store(file_path: &str) -> io::Result<()> {
+    for path in [
+        file_path.to_string(),
+        history_path(file_path),
+        token::tokens_path(file_path),
+    ] {
+        OpenOptions::new().append(true).create(true).open(path)?;
+    }
+    Ok(())
+}
+
+/// takes the lock on `users.lock` that marks one process as the owner of the
+/// store: the server for as long as it runs, or an admin command working on
+/// the files directly. the store lock only covers threads of one process;
+/// this keeps two processes from writing at once. released when the file is
+/// dropped or the process exits. `None` when another process holds it.
+pub fn claim_store(file_path: &str) -> io::Result<Option<File>> {
+    let path = match file_path.strip_suffix(".csv") {
+        Some(stem) => format!("{}.lock", stem),
+        None => format!("{}.lock", file_path),
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// flushes the store and its history to disk. called on shutdown with the
+/// write lock held, after the last write has finished.
+pub fn sync_to_disk(file_path: &str) -> io::Result<()> {
+    for path in [file_path.to_string(), history_path(file_path)] {
+        if let Some(file) = open_store(&path)? {
+            file.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// opens the store for reading. a store that doesn't exist yet is empty
+/// rather than an error; the first save creates it.
+fn open_store(file_path: &str) -> io::Result<Option<File>> {
+    match File::open(file_path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// previous versions live next to the store: `users.csv` -> `users.history.csv`.
+pub fn history_path(file_path: &str) -> String {
+    match file_path.strip_suffix(".csv") {
+        Some(stem) => format!("{}.history.csv", stem),
+        None => format!("{}.history", file_path),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::fs;
+
+    /// a store holding `fork` and `alice`, both live.
+    fn store(dir: &TempDir) -> String {
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, "fork,Rust,1\nalice,Haskell,2\n").unwrap();
+        file_path
+    }
+
+    #[test]
+    fn deleted_user_is_hidden_until_restored() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        User::remove_user(&file_path, "fork").unwrap();
+        assert!(matches!(
+            User::lookup_user(&file_path, "fork"),
+            Err(DatabaseError::UserNotFound)
+        ));
+        assert!(matches!(
+            User::remove_user(&file_path, "fork"),
+            Err(DatabaseError::UserNotFound)
+        ));
+
+        let restored = User::restore_user(&file_path, "fork").unwrap();
+        assert_eq!(restored.deleted_at, None);
+        assert_eq!(restored.languages, [Language::Rust]);
+        assert!(User::lookup_user(&file_path, "fork").is_ok());
+    }
+
+    #[test]
+    fn deleted_user_keeps_its_name() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        User::remove_user(&file_path, "fork").unwrap();
+        let again = User::create_user(Some("fork".to_string()), None, None).unwrap();
+        assert!(matches!(
+            again.save_to_csv(&file_path),
+            Err(DatabaseError::UserAlreadyExists)
+        ));
+    }
+
+    #[test]
+    fn only_deleted_users_can_be_restored() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        assert!(matches!(
+            User::restore_user(&file_path, "alice"),
+            Err(DatabaseError::UserNotDeleted)
+        ));
+        assert!(matches!(
+            User::restore_user(&file_path, "nobody"),
+            Err(DatabaseError::UserNotFound)
+        ));
+    }
+
+    #[test]
+    fn purge_drops_users_deleted_before_the_retention_window() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+        let rows = format!(
+            "fork,Rust,1,1000\nalice,Haskell,2\nbob,Go,3,{}\n",
+            now() - 60
+        );
+        fs::write(&file_path, rows).unwrap();
+
+        let purged = User::purge_deleted(&file_path, Duration::from_secs(60 * 60)).unwrap();
+        assert_eq!(purged, ["fork"]);
+        assert!(User::lookup_any(&file_path, "fork").unwrap().is_none());
+        // bob was deleted inside the window and can still come back
+        assert!(User::restore_user(&file_path, "bob").is_ok());
+        assert!(User::lookup_user(&file_path, "alice").is_ok());
+    }
+
+    #[test]
+    fn purge_with_nothing_to_drop_leaves_the_store_alone() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let purged = User::purge_deleted(&file_path, Duration::ZERO).unwrap();
+        assert!(purged.is_empty());
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "fork,Rust,1\nalice,Haskell,2\n"
+        );
+    }
+
+    #[test]
+    fn edits_are_archived_and_can_be_reverted() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let mut fork = User::lookup_user(&file_path, "fork").unwrap();
+        fork.add_language(vec![Language::C], &file_path).unwrap();
+        let history = User::history(&file_path, "fork").unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].user.languages, [Language::Rust]);
+        assert!(history[0].superseded_at.is_some());
+        assert_eq!(history[1].user.languages, [Language::Rust, Language::C]);
+        assert_eq!(history[1].superseded_at, None);
+
+        let reverted = User::revert_to(&file_path, "fork", 1).unwrap();
+        assert_eq!(reverted.languages, [Language::Rust]);
+        let history = User::history(&file_path, "fork").unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].user.languages, [Language::Rust, Language::C]);
+        assert_eq!(history[2].user.languages, [Language::Rust]);
+        assert_eq!(User::history(&file_path, "alice").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reverting_needs_a_live_user_and_a_known_version() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        assert!(matches!(
+            User::revert_to(&file_path, "fork", 2),
+            Err(DatabaseError::VersionNotFound)
+        ));
+        User::remove_user(&file_path, "fork").unwrap();
+        assert!(matches!(
+            User::revert_to(&file_path, "fork", 1),
+            Err(DatabaseError::UserNotFound)
+        ));
+    }
+
+    #[test]
+    fn version_at_finds_the_version_in_effect() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, "fork,Rust|C|Go,1\n").unwrap();
+        fs::write(
+            history_path(&file_path),
+            "1,1000,fork,Rust,1\n2,2000,fork,Rust|C,1\n",
+        )
+        .unwrap();
+
+        let version = |at| User::version_at(&file_path, "fork", at).unwrap().version;
+        assert_eq!(version(0), 1);
+        assert_eq!(version(999), 1);
+        assert_eq!(version(1000), 2);
+        assert_eq!(version(2000), 3);
+        assert_eq!(version(now()), 3);
+    }
+
+    #[test]
+    fn purge_drops_the_history_too() {
+        let dir = TempDir::new();
+        let file_path = dir.file("users.csv");
+        fs::write(&file_path, "fork,Rust,1,1000\nalice,Haskell,2\n").unwrap();
+        fs::write(
+            history_path(&file_path),
+            "1,900,fork,C,1\n1,900,alice,Go,2\n",
+        )
+        .unwrap();
+
+        User::purge_deleted(&file_path, Duration::from_secs(60 * 60)).unwrap();
+        assert_eq!(
+            fs::read_to_string(history_path(&file_path)).unwrap(),
+            "1,900,alice,Go,2\n"
+        );
+        assert_eq!(User::history(&file_path, "alice").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn validate_enforces_the_configured_limits() {
+        let limits = Config::get().limits;
+        let username = "a".repeat(limits.max_username_len + 1);
+        assert!(matches!(
+            User::validate(&username, &[], ""),
+            Err(DatabaseError::FieldTooLong {
+                field: "username",
+                ..
+            })
+        ));
+        let discord_id = "1".repeat(limits.max_discord_id_len + 1);
+        assert!(matches!(
+            User::validate("fork", &[], &discord_id),
+            Err(DatabaseError::FieldTooLong {
+                field: "discord id",
+                ..
+            })
+        ));
+        let languages = vec![Language::Rust; limits.max_languages + 1];
+        assert!(matches!(
+            User::validate("fork", &languages, ""),
+            Err(DatabaseError::TooManyLanguages { .. })
+        ));
+        assert!(User::validate("fork", &[Language::Rust], "1").is_ok());
+    }
+
+    #[test]
+    fn validate_refuses_what_would_break_the_csv() {
+        for username in ["a,b", "a|b", "a\nb"] {
+            assert!(
+                matches!(
+                    User::validate(username, &[], ""),
+                    Err(DatabaseError::ReservedCharacter {
+                        field: "username",
+                        ..
+                    })
+                ),
+                "{:?}",
+                username
+            );
+        }
+        assert!(matches!(
+            User::create_user(Some("  ".to_string()), None, None),
+            Err(DatabaseError::MissingUsername)
+        ));
+    }
+
+    #[test]
+    fn soft_delete_revokes_the_token() {
+        let dir = TempDir::new();
+        let file_path = store(&dir);
+
+        let issued = token::issue(&file_path, "fork").unwrap();
+        User::remove_user(&file_path, "fork").unwrap();
+        assert_eq!(token::owner(&file_path, &issued).unwrap(), None);
+    }
+}